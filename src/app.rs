@@ -1,4 +1,10 @@
+use super::layout::PackingAlgorithm;
+use super::modes::{LayoutMode, TreemapWeight};
+use super::output::{OutputFormat, PngCompression};
 use super::packer::*;
+use super::resampling::Resampling;
+use super::sheets::{SheetFormat, StyleSheet};
+use super::tonemapping::ToneMapping;
 use super::utils::*;
 use core::time::Duration;
 use eframe::{egui, epi};
@@ -6,7 +12,7 @@ use egui::*;
 use epi::Storage;
 // use image::RgbaImage;
 use plot::{Plot, PlotImage, Polygon, Value, Values};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug)]
 struct Settings {
@@ -15,6 +21,10 @@ struct Settings {
     ratio_string: String,
     ratio_custom: (usize, usize),
     zip: bool,
+    json: bool,
+    gif: bool,
+    sheet: Option<SheetFormat>,
+    style: Option<StyleSheet>,
     export_path: PathBuf,
 }
 impl Default for Settings {
@@ -25,6 +35,10 @@ impl Default for Settings {
             ratio_string: "2 : 1".to_string(),
             ratio_custom: (2, 1),
             zip: false,
+            json: false,
+            gif: false,
+            sheet: None,
+            style: None,
             export_path: default_path(),
         }
     }
@@ -96,6 +110,7 @@ impl epi::App for P3App {
         self.packer = Packer::new(
             self.settings.width,
             AspectRatio::default(),
+            PackingAlgorithm::default(),
             ImageScaling::default(),
             false,
         );
@@ -113,9 +128,32 @@ impl epi::App for P3App {
     fn save(&mut self, storage: &mut dyn epi::Storage) {
         epi::set_value(storage, "PPP_scale", &self.packer.scale);
         epi::set_value(storage, "PPP_equal", &self.packer.equal);
+        epi::set_value(storage, "PPP_trim", &self.packer.trim);
+        epi::set_value(storage, "PPP_split_frames", &self.packer.split_frames);
+        epi::set_value(storage, "PPP_extrude", &self.packer.extrude);
+        epi::set_value(storage, "PPP_align", &self.packer.align);
+        epi::set_value(storage, "PPP_channels", &self.packer.channels);
+        epi::set_value(storage, "PPP_output", &self.packer.output);
+        epi::set_value(storage, "PPP_resampling", &self.packer.resampling);
+        epi::set_value(storage, "PPP_pixel_art", &self.packer.pixel_art);
+        epi::set_value(storage, "PPP_blend", &self.packer.blend);
+        epi::set_value(storage, "PPP_sixteen_bit", &self.packer.sixteen_bit);
+        epi::set_value(storage, "PPP_tone_mapping", &self.packer.tone_mapping);
         epi::set_value(storage, "PPP_ratio", &self.packer.aspect);
+        epi::set_value(storage, "PPP_mode", &self.packer.mode);
+        epi::set_value(storage, "PPP_grid", &self.packer.grid);
+        epi::set_value(storage, "PPP_masonry", &self.packer.masonry);
+        epi::set_value(storage, "PPP_treemap", &self.packer.treemap);
+        epi::set_value(storage, "PPP_algorithm", &self.packer.algorithm);
+        epi::set_value(storage, "PPP_rotate", &self.packer.rotate);
+        epi::set_value(storage, "PPP_optimizer", &self.packer.optimizer);
+        epi::set_value(storage, "PPP_paging", &self.packer.paging);
         epi::set_value(storage, "PPP_export_path", &self.settings.export_path);
         epi::set_value(storage, "PPP_zip", &self.settings.zip);
+        epi::set_value(storage, "PPP_json", &self.settings.json);
+        epi::set_value(storage, "PPP_gif", &self.settings.gif);
+        epi::set_value(storage, "PPP_sheet", &self.settings.sheet);
+        epi::set_value(storage, "PPP_style", &self.settings.style);
     }
 
     fn update(&mut self, ctx: &egui::CtxRef, frame: &mut epi::Frame<'_>) {
@@ -329,6 +367,179 @@ impl P3App {
                             self.update_packer(&[]);
                         }
                     });
+                    //RADIO - LAYOUT MODE
+                    ui.separator();
+                    ui.horizontal(|mode| {
+                        let tooltip_mode = "How images are arranged..\nUpdates package on change..";
+                        mode.label("Layout:").on_hover_text(tooltip_mode);
+                        for option in LayoutMode::ALL {
+                            if mode
+                                .selectable_value(&mut self.packer.mode, option, option.name())
+                                .clicked()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        }
+                    });
+                    //GRID SETTINGS
+                    if matches!(self.packer.mode, LayoutMode::Grid | LayoutMode::Frames) {
+                        ui.horizontal(|grid| {
+                            let tooltip_grid =
+                                "Number of cells, 0 is automatic..\nUpdates package on change..";
+                            grid.label("Columns:").on_hover_text(tooltip_grid);
+                            let columns = grid.add(
+                                DragValue::new(&mut self.packer.grid.columns).clamp_range(0..=100),
+                            );
+                            grid.label("Rows:").on_hover_text(tooltip_grid);
+                            let rows = grid.add(
+                                DragValue::new(&mut self.packer.grid.rows).clamp_range(0..=100),
+                            );
+                            let fill = match self.packer.mode {
+                                LayoutMode::Grid => grid
+                                    .checkbox(&mut self.packer.grid.fill, "Fill")
+                                    .on_hover_text("Crop images to fill cells.."),
+                                _ => grid
+                                    .checkbox(&mut self.packer.split_frames, "Split Animations")
+                                    .on_hover_text(
+                                        "Add frames of animated GIF and APNG as separate images..\nApplies to images added next..",
+                                    ),
+                            };
+                            if columns.changed() || rows.changed() || fill.clicked() {
+                                self.update_packer(&[]);
+                            }
+                        });
+                    }
+                    //MASONRY SETTINGS
+                    if self.packer.mode == LayoutMode::Masonry {
+                        ui.horizontal(|masonry| {
+                            let tooltip_columns =
+                                "Number of columns, 0 is automatic..\nUpdates package on change..";
+                            masonry.label("Columns:").on_hover_text(tooltip_columns);
+                            if masonry
+                                .add(
+                                    DragValue::new(&mut self.packer.masonry.columns)
+                                        .clamp_range(0..=100),
+                                )
+                                .changed()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        });
+                    }
+                    //RADIO - TREEMAP WEIGHT
+                    if self.packer.mode == LayoutMode::Treemap {
+                        ui.horizontal(|weight| {
+                            let tooltip_weight =
+                                "What makes image bigger..\nUpdates package on change..";
+                            weight.label("Weight:").on_hover_text(tooltip_weight);
+                            //Priorities are set only from command line
+                            for option in TreemapWeight::ALL
+                                .into_iter()
+                                .filter(|option| *option != TreemapWeight::Priority)
+                            {
+                                if weight
+                                    .selectable_value(
+                                        &mut self.packer.treemap.weight,
+                                        option,
+                                        option.name(),
+                                    )
+                                    .clicked()
+                                {
+                                    self.update_packer(&[]);
+                                }
+                            }
+                        });
+                    }
+                    //RADIO - PACKING ALGORITHM
+                    ui.horizontal(|algorithm| {
+                        algorithm.set_enabled(self.packer.mode == LayoutMode::Packing);
+                        let tooltip_algorithm =
+                            "Heuristic used to place images..\nUpdates package on change..";
+                        algorithm
+                            .label("Algorithm:")
+                            .on_hover_text(tooltip_algorithm);
+                        for option in PackingAlgorithm::ALL {
+                            if algorithm
+                                .selectable_value(&mut self.packer.algorithm, option, option.name())
+                                .clicked()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        }
+                        algorithm.separator();
+                        if algorithm
+                            .checkbox(&mut self.packer.rotate, "Rotate")
+                            .on_hover_text("Allow rotating images by 90° for denser packing..")
+                            .clicked()
+                        {
+                            self.update_packer(&[]);
+                        }
+                    });
+                    //OPTIMIZER SETTINGS
+                    ui.horizontal(|optimizer| {
+                        optimizer.set_enabled(
+                            self.packer.mode == LayoutMode::Packing
+                                && self.packer.algorithm.engine().keeps_order(),
+                        );
+                        let tooltip_optimize =
+                            "Try several orders of images and keep the densest package..\nUpdates package on change..";
+                        let enabled = optimizer
+                            .checkbox(&mut self.packer.optimizer.enabled, "Optimize")
+                            .on_hover_text(tooltip_optimize);
+                        optimizer.label("Attempts:");
+                        let attempts = optimizer.add(
+                            DragValue::new(&mut self.packer.optimizer.attempts).clamp_range(1..=1000),
+                        );
+                        optimizer.label("Seconds:");
+                        let seconds = optimizer
+                            .add(
+                                DragValue::new(&mut self.packer.optimizer.seconds)
+                                    .clamp_range(0.0..=60.0)
+                                    .speed(0.1),
+                            )
+                            .on_hover_text("Time limit, 0 is unlimited..");
+                        optimizer.label("Seed:");
+                        let seed = optimizer
+                            .add(DragValue::new(&mut self.packer.optimizer.seed))
+                            .on_hover_text("Seed of random orders..");
+                        if enabled.clicked()
+                            || (self.packer.optimizer.enabled
+                                && (attempts.changed() || seconds.changed() || seed.changed()))
+                        {
+                            self.update_packer(&[]);
+                        }
+                    });
+                    //PAGES SETTINGS
+                    ui.horizontal(|pages| {
+                        pages.set_enabled(self.packer.mode == LayoutMode::Packing);
+                        let tooltip_pages =
+                            "Maximum size of each page, extra pages are added when images don't fit..\nUpdates package on change..";
+                        pages.label("Pages:").on_hover_text(tooltip_pages);
+                        for (max_size, name) in
+                            [(0, "Off"), (1024, "1K"), (2048, "2K"), (4096, "4K"), (8192, "8K")]
+                        {
+                            if pages
+                                .selectable_value(&mut self.packer.paging.max_size, max_size, name)
+                                .on_hover_text(tooltip_pages)
+                                .clicked()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        }
+                        pages.separator();
+                        if pages
+                            .checkbox(&mut self.packer.paging.power_of_two, "Power of 2")
+                            .on_hover_text("Round page sides up to power of two..\nMaximum size is rounded down to it..")
+                            .clicked()
+                            && self.packer.paging.max_size > 0
+                        {
+                            self.update_packer(&[]);
+                        }
+                        if self.packer.page_count() > 1 {
+                            pages.separator();
+                            pages.label(format!("{} pages", self.packer.page_count()));
+                        }
+                    });
                     //Thumbnails scaling options
                     ui.separator();
                     ui.horizontal(|scaling| {
@@ -342,6 +553,23 @@ impl P3App {
                         {
                             self.update_packer(&[]);
                         };
+                        if scaling
+                            .checkbox(&mut self.packer.trim, "Trim")
+                            .on_hover_text("Cut off transparent borders of images..")
+                            .clicked()
+                        {
+                            self.update_packer(&[]);
+                        };
+                        if scaling
+                            .add_enabled(
+                                self.packer.mode == LayoutMode::Packing,
+                                Checkbox::new(&mut self.packer.pixel_art, "Pixel Art"),
+                            )
+                            .on_hover_text("Scale images by whole numbers and never blend pixels..\nWorks in packing mode, images are never shrunk below actual size..")
+                            .clicked()
+                        {
+                            self.update_packer(&[]);
+                        };
                         scaling.separator();
 
                         let tooltip_margin = "Space between images..\nUpdates package on change..";
@@ -388,9 +616,63 @@ impl P3App {
                             ))
                             .on_hover_text(tooltip_margin);
                     });
+                    //ATLAS EDGES
+                    ui.horizontal(|edges| {
+                        let tooltip_extrude =
+                            "Repeat edge pixels of images into margin, so filtering doesn't bleed neighbours..\nUpdates package on change..";
+                        edges.label("Extrude:").on_hover_text(tooltip_extrude);
+                        if edges
+                            .add(DragValue::new(&mut self.packer.extrude).clamp_range(0..=16))
+                            .on_hover_text(tooltip_extrude)
+                            .changed()
+                        {
+                            self.update_packer(&[]);
+                        }
+                        edges.separator();
+                        edges.set_enabled(self.packer.mode == LayoutMode::Packing);
+                        let tooltip_align =
+                            "Place images at multiples of pixels, holds for actual size..\nUpdates package on change..";
+                        edges.label("Align:").on_hover_text(tooltip_align);
+                        for (align, name) in [(0, "Off"), (4, "4"), (8, "8"), (16, "16")] {
+                            if edges
+                                .selectable_value(&mut self.packer.align, align, name)
+                                .on_hover_text(tooltip_align)
+                                .clicked()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        }
+                    });
+                    //CHANNELS
+                    ui.horizontal(|channels| {
+                        let tooltip_channels =
+                            "Pack images once and render the same layout for companions with suffixes,\nlike normal or roughness maps..\nUpdates package on change..";
+                        if channels
+                            .checkbox(&mut self.packer.channels.enabled, "Channels:")
+                            .on_hover_text(tooltip_channels)
+                            .clicked()
+                        {
+                            self.update_packer(&[]);
+                        }
+                        let suffixes = channels
+                            .add_enabled(
+                                self.packer.channels.enabled,
+                                egui::TextEdit::singleline(&mut self.packer.channels.suffixes)
+                                    .desired_width(120.0),
+                            )
+                            .on_hover_text("Suffixes of companion images, separated by commas..");
+                        if suffixes.gained_focus() {
+                            self.shortcuts = false;
+                        }
+                        if suffixes.lost_focus() {
+                            self.shortcuts = true;
+                            self.update_packer(&[]);
+                        }
+                    });
                     //RADIO - EXPORT SIZE
                     ui.separator();
                     ui.horizontal(|export_size| {
+                        export_size.set_enabled(!self.packer.paged());
                         let tooltip_size = "Maximum dimension of exported image..";
                         export_size
                             .label("Export Size:")
@@ -444,6 +726,201 @@ impl P3App {
                             // println!("{:?}", self.packer.scale);
                         }
                     });
+                    if self.packer.paged() {
+                        ui.label("Pages are exported in actual size.");
+                    } else if self.packer.pixel_art_overflows() {
+                        ui.label("Pixel art is exported in actual size, it is larger than export size.");
+                    }
+                    //RADIO - RESAMPLING FILTER
+                    ui.horizontal(|filter| {
+                        let tooltip_filter =
+                            "How images are resized..\nUpdates package on change..";
+                        filter.label("Filter:").on_hover_text(tooltip_filter);
+                        let mut changed = false;
+                        filter.add_enabled_ui(!self.packer.pixel_art(), |options| {
+                            for option in Resampling::ALL {
+                                changed |= options
+                                    .selectable_value(
+                                        &mut self.packer.resampling.filter,
+                                        option,
+                                        option.name(),
+                                    )
+                                    .clicked();
+                            }
+                            options.separator();
+                            changed |= options
+                                .checkbox(&mut self.packer.resampling.linear, "Linear")
+                                .on_hover_text("Blend colors in linear light..\nDownscaled images keep their brightness..")
+                                .clicked();
+                        });
+                        filter.separator();
+                        changed |= filter
+                            .checkbox(&mut self.packer.blend, "Blend")
+                            .on_hover_text("Lay transparent images over ones under them..\nOtherwise they replace pixels under them..")
+                            .clicked();
+                        if changed {
+                            self.update_packer(&[]);
+                        }
+                    });
+                    //RADIO - TONE MAPPING
+                    ui.horizontal(|tone| {
+                        let tooltip_tone =
+                            "How light of HDR and EXR images is fit to output..\nUpdates package on change..";
+                        tone.label("HDR:").on_hover_text(tooltip_tone);
+                        let mut changed = false;
+                        for option in ToneMapping::ALL {
+                            changed |= tone
+                                .selectable_value(
+                                    &mut self.packer.tone_mapping.curve,
+                                    option,
+                                    option.name(),
+                                )
+                                .on_hover_text(tooltip_tone)
+                                .clicked();
+                        }
+                        tone.separator();
+                        tone.label("Exposure:")
+                            .on_hover_text("Stops of light added before tone mapping..");
+                        changed |= tone
+                            .add(
+                                DragValue::new(&mut self.packer.tone_mapping.exposure)
+                                    .speed(0.1)
+                                    .clamp_range(-16.0..=16.0),
+                            )
+                            .changed();
+                        if changed {
+                            self.update_packer(&[]);
+                        }
+                    });
+                    //RADIO - OUTPUT FORMAT
+                    ui.horizontal(|format| {
+                        let tooltip_format = "File format of resulting image..";
+                        format.label("Format:").on_hover_text(tooltip_format);
+                        for option in OutputFormat::ALL {
+                            format
+                                .selectable_value(
+                                    &mut self.packer.output.format,
+                                    option,
+                                    option.name(),
+                                )
+                                .on_hover_text(tooltip_format);
+                        }
+                        format.separator();
+                        match self.packer.output.format {
+                            OutputFormat::Png => {
+                                format
+                                    .label("Compression:")
+                                    .on_hover_text("Smaller file takes longer to save..");
+                                for option in PngCompression::ALL {
+                                    format.selectable_value(
+                                        &mut self.packer.output.compression,
+                                        option,
+                                        option.name(),
+                                    );
+                                }
+                            }
+                            OutputFormat::Jpeg => {
+                                format.label("Quality:").on_hover_text("Higher is larger file..");
+                                format.add(
+                                    DragValue::new(&mut self.packer.output.quality)
+                                        .clamp_range(1..=100),
+                                );
+                                format
+                                    .checkbox(&mut self.packer.output.flatten, "Flatten")
+                                    .on_hover_text("JPEG has no transparency..\nLay images over background color..");
+                                format.add_enabled_ui(self.packer.output.flatten, |color| {
+                                    color.color_edit_button_srgb(&mut self.packer.output.background);
+                                });
+                            }
+                            OutputFormat::WebP => {
+                                format
+                                    .label("Lossless")
+                                    .on_hover_text("WebP keeps every pixel as is..");
+                            }
+                            _ => (),
+                        }
+                        format
+                            .add_enabled(
+                                self.packer.output.format.supports_sixteen_bit(),
+                                Checkbox::new(&mut self.packer.sixteen_bit, "16-bit"),
+                            )
+                            .on_hover_text("Keep 16 bits per channel of deep images..\nOnly PNG and TIFF can hold them..");
+                    });
+                    //SPRITE SHEET DESCRIPTOR
+                    ui.horizontal(|sheet| {
+                        let tooltip_sheet = "Also save sprite sheet descriptor for game engine..";
+                        sheet.label("Sheet:").on_hover_text(tooltip_sheet);
+                        sheet
+                            .selectable_value(&mut self.settings.sheet, None, "Off")
+                            .on_hover_text(tooltip_sheet);
+                        for format in SheetFormat::ALL {
+                            let tooltip_format = match format.supports_rotation() {
+                                true => tooltip_sheet,
+                                false => "Rotated images are not supported..",
+                            };
+                            sheet
+                                .selectable_value(&mut self.settings.sheet, Some(format), format.name())
+                                .on_hover_text(tooltip_format);
+                        }
+                    });
+                    //STYLESHEET
+                    ui.horizontal(|style| {
+                        let tooltip_style = "Also save stylesheet with class for each image..";
+                        style.label("Style:").on_hover_text(tooltip_style);
+                        style
+                            .selectable_value(&mut self.settings.style, None, "Off")
+                            .on_hover_text(tooltip_style);
+                        for format in StyleSheet::ALL {
+                            style
+                                .selectable_value(&mut self.settings.style, Some(format), format.name())
+                                .on_hover_text(tooltip_style);
+                        }
+                    });
+                    //LAYOUT FILE
+                    ui.horizontal(|layout| {
+                        let save = layout.add_enabled(
+                            self.counter.total > 0,
+                            Button::new("Save Layout..."),
+                        );
+                        if save
+                            .on_hover_text("Save positions of images to render them again later..")
+                            .clicked()
+                        {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Layout", &["ppp"])
+                                .set_directory(&self.settings.export_path)
+                                .set_file_name(&format!("{}.{}", file_timestamp(), "ppp"))
+                                .save_file()
+                            {
+                                if let Err(err) = self.packer.save_layout(&path) {
+                                    println!("Couldn't save layout file!\n{}", err);
+                                }
+                            }
+                        }
+                        if layout
+                            .button("Load Layout...")
+                            .on_hover_text("Render saved layout again, without packing..")
+                            .clicked()
+                        {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("Layout", &["ppp"])
+                                .set_directory(&self.settings.export_path)
+                                .pick_file()
+                            {
+                                self.load_layout(&path);
+                            }
+                        }
+                        if self.packer.fixed {
+                            layout.separator();
+                            if layout
+                                .checkbox(&mut self.packer.fixed, "Fixed")
+                                .on_hover_text("Keep loaded layout..\nUncheck to pack images again..")
+                                .clicked()
+                            {
+                                self.update_packer(&[]);
+                            }
+                        }
+                    });
                     ui.separator();
                     //BUTTONS - EXPORT
                     ui.horizontal(|buttons| {
@@ -488,6 +965,12 @@ impl P3App {
                         buttons
                             .checkbox(&mut self.settings.zip, "ZIP")
                             .on_hover_text("Also pack all source images to archive..");
+                        buttons
+                            .checkbox(&mut self.settings.json, "JSON")
+                            .on_hover_text("Also save positions of images to JSON file..");
+                        buttons
+                            .checkbox(&mut self.settings.gif, "GIF")
+                            .on_hover_text("Also save animated GIF where animated images play..");
 
                         buttons.separator();
                         if self.counter.total > 0 {
@@ -501,7 +984,13 @@ impl P3App {
                                             &self.packer.aspect,
                                         ),
                                     };
-                                    format!("Save result to file..\n{} x {}", size.w, size.h)
+                                    match self.packer.page_count() {
+                                        1 => format!("Save result to file..\n{} x {}", size.w, size.h),
+                                        pages => format!(
+                                            "Save result to files..\n{} pages up to {} x {}",
+                                            pages, size.w, size.h
+                                        ),
+                                    }
                                 })
                                 .clicked()
                             {
@@ -550,6 +1039,15 @@ impl P3App {
         self.counter.renew(self.packer.update(files));
     }
 
+    fn load_layout(&mut self, path: &Path) {
+        self.fader("loading");
+        match self.packer.load_layout(path) {
+            Ok(num) => self.counter.renew(num),
+            Err(err) => println!("Couldn't load layout!\n{}", err),
+        }
+        self.fader("");
+    }
+
     fn handle_keys(&mut self, ctx: &egui::CtxRef) {
         for event in &ctx.input().raw.events {
             match event {
@@ -581,12 +1079,32 @@ impl P3App {
     // Shortcut Functions
     fn clear(&mut self) {
         self.fader("clear");
+        let old = std::mem::take(&mut self.packer);
         self.packer = Packer::new(
             self.settings.width,
-            self.packer.aspect,
-            self.packer.scale,
-            self.packer.equal,
+            old.aspect,
+            old.algorithm,
+            old.scale,
+            old.equal,
         );
+        self.packer.mode = old.mode;
+        self.packer.rotate = old.rotate;
+        self.packer.optimizer = old.optimizer;
+        self.packer.paging = old.paging;
+        self.packer.trim = old.trim;
+        self.packer.split_frames = old.split_frames;
+        self.packer.extrude = old.extrude;
+        self.packer.align = old.align;
+        self.packer.channels = old.channels;
+        self.packer.output = old.output;
+        self.packer.resampling = old.resampling;
+        self.packer.pixel_art = old.pixel_art;
+        self.packer.blend = old.blend;
+        self.packer.sixteen_bit = old.sixteen_bit;
+        self.packer.tone_mapping = old.tone_mapping;
+        self.packer.grid = old.grid;
+        self.packer.masonry = old.masonry;
+        self.packer.treemap = old.treemap;
         self.fader("");
         self.counter.reset();
     }
@@ -602,8 +1120,14 @@ impl P3App {
     }
     fn export(&mut self) {
         self.fader("exporting");
-        self.packer
-            .export(&self.settings.export_path, self.settings.zip);
+        self.packer.export(
+            &self.settings.export_path,
+            self.settings.zip,
+            self.settings.json,
+            self.settings.gif,
+            self.settings.sheet,
+            self.settings.style,
+        );
         self.fader("");
     }
     fn window_ratio(&mut self, ctx: &egui::CtxRef) {
@@ -617,10 +1141,39 @@ impl P3App {
         if let Some(storage) = storage {
             self.packer.scale = epi::get_value(storage, "PPP_scale").unwrap_or_default();
             self.packer.equal = epi::get_value(storage, "PPP_equal").unwrap_or_default();
+            self.packer.trim = epi::get_value(storage, "PPP_trim").unwrap_or_default();
+            self.packer.split_frames =
+                epi::get_value(storage, "PPP_split_frames").unwrap_or_default();
+            self.packer.extrude = epi::get_value(storage, "PPP_extrude").unwrap_or_default();
+            self.packer.align = epi::get_value(storage, "PPP_align").unwrap_or_default();
+            self.packer.channels = epi::get_value(storage, "PPP_channels").unwrap_or_default();
+            self.packer.output = epi::get_value(storage, "PPP_output").unwrap_or_default();
+            self.packer.resampling = epi::get_value(storage, "PPP_resampling").unwrap_or_default();
+            self.packer.pixel_art = epi::get_value(storage, "PPP_pixel_art").unwrap_or_default();
+            self.packer.blend = epi::get_value(storage, "PPP_blend").unwrap_or_default();
+            self.packer.sixteen_bit =
+                epi::get_value(storage, "PPP_sixteen_bit").unwrap_or_default();
+            self.packer.tone_mapping =
+                epi::get_value(storage, "PPP_tone_mapping").unwrap_or_default();
             self.packer.aspect = epi::get_value(storage, "PPP_ratio").unwrap_or_default();
+            self.packer.mode = epi::get_value(storage, "PPP_mode").unwrap_or_default();
+            self.packer.grid = epi::get_value(storage, "PPP_grid").unwrap_or_default();
+            self.packer.masonry = epi::get_value(storage, "PPP_masonry").unwrap_or_default();
+            self.packer.treemap = epi::get_value(storage, "PPP_treemap").unwrap_or_default();
+            if self.packer.treemap.weight == TreemapWeight::Priority {
+                self.packer.treemap.weight = TreemapWeight::default();
+            }
+            self.packer.algorithm = epi::get_value(storage, "PPP_algorithm").unwrap_or_default();
+            self.packer.rotate = epi::get_value(storage, "PPP_rotate").unwrap_or_default();
+            self.packer.optimizer = epi::get_value(storage, "PPP_optimizer").unwrap_or_default();
+            self.packer.paging = epi::get_value(storage, "PPP_paging").unwrap_or_default();
             self.settings.export_path =
                 epi::get_value(storage, "PPP_export_path").unwrap_or_else(default_path);
             self.settings.zip = epi::get_value(storage, "PPP_zip").unwrap_or_default();
+            self.settings.json = epi::get_value(storage, "PPP_json").unwrap_or_default();
+            self.settings.gif = epi::get_value(storage, "PPP_gif").unwrap_or_default();
+            self.settings.sheet = epi::get_value(storage, "PPP_sheet").unwrap_or_default();
+            self.settings.style = epi::get_value(storage, "PPP_style").unwrap_or_default();
         }
     }
 }
@@ -0,0 +1,141 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Companion images share layout of primary ones, every suffix gets its own output.
+/// With suffix `_n`, `rock_n.png` is normal map of `rock.png` from the same folder.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelSettings {
+    pub enabled: bool,
+    pub suffixes: String,
+}
+impl Default for ChannelSettings {
+    fn default() -> Self {
+        ChannelSettings {
+            enabled: false,
+            suffixes: "_n, _r, _ao".to_string(),
+        }
+    }
+}
+
+impl ChannelSettings {
+    pub fn suffixes(&self) -> Vec<&str> {
+        self.suffixes
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|suffix| !suffix.is_empty())
+            .collect()
+    }
+
+    /// Primary image from `files` that `file` is a companion of.
+    pub fn primary_of(&self, file: &Path, files: &Stems) -> Option<PathBuf> {
+        if !self.enabled {
+            return None;
+        }
+        let name = stem(file);
+        self.suffixes()
+            .iter()
+            .filter_map(|suffix| name.strip_suffix(suffix))
+            .find_map(|base| files.find(file.parent(), base))
+    }
+
+    /// Companion of `primary` image with given `suffix`, searched among `files` and then on disk.
+    pub fn companion(&self, primary: &Path, suffix: &str, files: &Stems) -> Option<PathBuf> {
+        let name = format!("{}{}", stem(primary), suffix);
+        if let Some(found) = files.find(primary.parent(), &name) {
+            return Some(found);
+        }
+        let extension = primary.extension().unwrap_or_default().to_string_lossy();
+        let beside = primary.with_file_name(format!("{}.{}", name, extension));
+        beside.is_file().then_some(beside)
+    }
+}
+
+/// Files by folder and stem, built once to search companions without scanning all files.
+pub struct Stems<'a>(HashMap<(Option<&'a Path>, String), &'a Path>);
+
+impl<'a> Stems<'a> {
+    pub fn new(files: impl IntoIterator<Item = &'a Path>) -> Self {
+        Stems(
+            files
+                .into_iter()
+                .map(|file| ((file.parent(), stem(file)), file))
+                .collect(),
+        )
+    }
+
+    fn find(&self, folder: Option<&Path>, stem: &str) -> Option<PathBuf> {
+        self.0
+            .get(&(folder, stem.to_string()))
+            .map(|file| file.to_path_buf())
+    }
+}
+
+fn stem(file: &Path) -> String {
+    file.file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings() -> ChannelSettings {
+        ChannelSettings {
+            enabled: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn companions_are_matched_by_suffix() {
+        let files = [
+            Path::new("rocks/rock.png"),
+            Path::new("rocks/rock_n.png"),
+            Path::new("rocks/rock_ao.png"),
+            Path::new("other/rock_r.png"),
+            Path::new("rocks/sand_n.png"),
+        ];
+        let stems = Stems::new(files);
+        let settings = settings();
+        assert_eq!(
+            settings.primary_of(files[1], &stems),
+            Some(PathBuf::from("rocks/rock.png"))
+        );
+        assert_eq!(
+            settings.primary_of(files[2], &stems),
+            Some(PathBuf::from("rocks/rock.png"))
+        );
+        //Companions are searched only in the same folder
+        assert_eq!(settings.primary_of(files[3], &stems), None);
+        assert_eq!(settings.primary_of(files[0], &stems), None);
+        assert_eq!(
+            settings.companion(files[0], "_n", &stems),
+            Some(PathBuf::from("rocks/rock_n.png"))
+        );
+        let disabled = ChannelSettings::default();
+        assert_eq!(disabled.primary_of(files[1], &stems), None);
+    }
+
+    #[test]
+    fn missing_companion_is_none() {
+        let files = [
+            Path::new("missing_folder/rock.png"),
+            Path::new("missing_folder/sand_n.png"),
+        ];
+        let stems = Stems::new(files);
+        let settings = settings();
+        assert_eq!(settings.companion(files[0], "_r", &stems), None);
+        assert_eq!(settings.primary_of(files[1], &stems), None);
+    }
+
+    #[test]
+    fn companion_is_found_on_disk() {
+        let folder = std::env::temp_dir().join("ppp_channels");
+        std::fs::create_dir_all(&folder).unwrap();
+        let (primary, normal) = (folder.join("wall.png"), folder.join("wall_n.png"));
+        std::fs::write(&normal, []).unwrap();
+        let stems = Stems::new([primary.as_path()]);
+        assert_eq!(settings().companion(&primary, "_n", &stems), Some(normal));
+    }
+}
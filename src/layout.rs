@@ -0,0 +1,472 @@
+use super::loader::Pic;
+use crunch::{pack, Item, Rect, Rotation};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Items placed on canvas, each one with its bounding rectangle.
+pub type Positions = Vec<(Rect, Pic)>;
+
+/// Total width of canvas, height of content and positions on it.
+pub type Packed = (usize, usize, Positions);
+
+#[derive(Debug)]
+pub enum PackingError {
+    NoImages,
+    DoesNotFit(usize),
+    PageTooSmall,
+}
+
+impl fmt::Display for PackingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PackingError::NoImages => write!(f, "Nothing to pack."),
+            PackingError::DoesNotFit(iterations) => {
+                write!(f, "Images don't fit after {} attempts.", iterations)
+            }
+            PackingError::PageTooSmall => write!(f, "Page is too small for margins."),
+        }
+    }
+}
+
+/// Strategy used by `Packer` to place items inside an area of fixed size.
+pub trait LayoutEngine {
+    /// Places all items inside `area` in given order. Fails if any item doesn't fit.
+    fn pack(&self, area: Rect, items: &[Item<Pic>]) -> Result<Positions, ()>;
+
+    /// Places the longest run of first items that fits inside `area`.
+    /// Returns their positions and how many of them were placed.
+    fn pack_some(&self, area: Rect, items: &[Item<Pic>]) -> (Positions, usize) {
+        if let Ok(positions) = self.pack(area, items) {
+            return (positions, items.len());
+        }
+        let (mut low, mut high) = (0, items.len() - 1);
+        let mut placed = Positions::new();
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            match self.pack(area, &items[..middle]) {
+                Ok(positions) => {
+                    low = middle;
+                    placed = positions;
+                }
+                Err(()) => high = middle - 1,
+            }
+        }
+        (placed, low)
+    }
+
+    /// Whether result depends on order of items.
+    fn keeps_order(&self) -> bool {
+        true
+    }
+}
+
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PackingAlgorithm {
+    #[default]
+    Crunch,
+    MaxRects,
+    Skyline,
+    Shelf,
+}
+
+impl PackingAlgorithm {
+    pub const ALL: [PackingAlgorithm; 4] = [
+        PackingAlgorithm::Crunch,
+        PackingAlgorithm::MaxRects,
+        PackingAlgorithm::Skyline,
+        PackingAlgorithm::Shelf,
+    ];
+
+    pub fn engine(&self) -> &'static dyn LayoutEngine {
+        match self {
+            PackingAlgorithm::Crunch => &CrunchEngine,
+            PackingAlgorithm::MaxRects => &MaxRectsEngine,
+            PackingAlgorithm::Skyline => &SkylineEngine,
+            PackingAlgorithm::Shelf => &ShelfEngine,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PackingAlgorithm::Crunch => "Crunch",
+            PackingAlgorithm::MaxRects => "MaxRects",
+            PackingAlgorithm::Skyline => "Skyline",
+            PackingAlgorithm::Shelf => "Shelf",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PackingAlgorithm::ALL
+            .iter()
+            .find(|algorithm| algorithm.name().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+}
+
+/// Guillotine-like packer from `crunch` crate. Sorts items by itself.
+pub struct CrunchEngine;
+impl LayoutEngine for CrunchEngine {
+    fn pack(&self, area: Rect, items: &[Item<Pic>]) -> Result<Positions, ()> {
+        //Crunch doesn't report rotation, so every item carries its own cell to compare with
+        let cells = items.iter().map(|item| {
+            Item::new(
+                (item.w, item.h, item.rot, item.data.clone()),
+                item.w,
+                item.h,
+                item.rot,
+            )
+        });
+        match pack(area, cells) {
+            Ok(packed) => Ok(packed
+                .into_iter()
+                .map(|(rect, (w, h, rotation, pic))| {
+                    let rotated = rotation == Rotation::Allowed && rect.w == h && rect.w != w;
+                    (rect, Pic { rotated, ..pic })
+                })
+                .collect()),
+            Err(_) => Err(()),
+        }
+    }
+
+    fn keeps_order(&self) -> bool {
+        false
+    }
+}
+
+/// MaxRects with Best Short Side Fit heuristic.
+pub struct MaxRectsEngine;
+impl LayoutEngine for MaxRectsEngine {
+    fn pack(&self, area: Rect, items: &[Item<Pic>]) -> Result<Positions, ()> {
+        let mut free = vec![area];
+        let mut placed = Positions::with_capacity(items.len());
+        for item in items {
+            let (rect, rotated) = orientations(item)
+                .flat_map(|(w, h, rotated)| {
+                    free.iter()
+                        .filter(move |r| w <= r.w && h <= r.h)
+                        .map(move |r| (Rect::new(r.x, r.y, w, h), rotated, r))
+                })
+                .min_by_key(|(rect, _, r)| {
+                    let (dw, dh) = (r.w - rect.w, r.h - rect.h);
+                    (dw.min(dh), dw.max(dh))
+                })
+                .map(|(rect, rotated, _)| (rect, rotated))
+                .ok_or(())?;
+            split_free_rects(&mut free, &rect);
+            placed.push((
+                rect,
+                Pic {
+                    rotated,
+                    ..item.data.clone()
+                },
+            ));
+        }
+        Ok(placed)
+    }
+}
+
+//Sizes the item may be placed with, rotated one goes second
+fn orientations(item: &Item<Pic>) -> impl Iterator<Item = (usize, usize, bool)> {
+    let rotatable = item.rot == Rotation::Allowed && item.w != item.h;
+    [(item.w, item.h, false), (item.h, item.w, true)]
+        .into_iter()
+        .take(if rotatable { 2 } else { 1 })
+}
+
+fn split_free_rects(free: &mut Vec<Rect>, used: &Rect) {
+    let mut splits = Vec::<Rect>::new();
+    free.retain(|r| {
+        if !r.overlaps(used) {
+            return true;
+        }
+        if used.x > r.x {
+            splits.push(Rect::new(r.x, r.y, used.x - r.x, r.h));
+        }
+        if used.right() < r.right() {
+            splits.push(Rect::new(used.right(), r.y, r.right() - used.right(), r.h));
+        }
+        if used.y > r.y {
+            splits.push(Rect::new(r.x, r.y, r.w, used.y - r.y));
+        }
+        if used.bottom() < r.bottom() {
+            splits.push(Rect::new(
+                r.x,
+                used.bottom(),
+                r.w,
+                r.bottom() - used.bottom(),
+            ));
+        }
+        false
+    });
+    free.extend(splits);
+
+    //Remove free rects that are contained by others
+    let mut i = 0;
+    while i < free.len() {
+        let redundant = free
+            .iter()
+            .enumerate()
+            .any(|(j, other)| j != i && other.contains(&free[i]) && (other != &free[i] || j < i));
+        if redundant {
+            free.swap_remove(i);
+        } else {
+            i += 1;
+        }
+    }
+}
+
+/// Bottom-Left Skyline packer.
+pub struct SkylineEngine;
+
+struct Segment {
+    x: usize,
+    y: usize,
+    w: usize,
+}
+
+impl LayoutEngine for SkylineEngine {
+    fn pack(&self, area: Rect, items: &[Item<Pic>]) -> Result<Positions, ()> {
+        let mut skyline = vec![Segment {
+            x: area.x,
+            y: area.y,
+            w: area.w,
+        }];
+        let mut placed = Positions::with_capacity(items.len());
+        for item in items {
+            let mut best: Option<(Rect, usize, bool)> = None;
+            for (w, h, rotated) in orientations(item) {
+                for i in 0..skyline.len() {
+                    if let Some(y) = skyline_fit(&skyline, i, w, &area) {
+                        let x = skyline[i].x;
+                        if y + h <= area.bottom()
+                            && best.is_none_or(|(rect, _, _)| (y, x) < (rect.y, rect.x))
+                        {
+                            best = Some((Rect::new(x, y, w, h), i, rotated));
+                        }
+                    }
+                }
+            }
+            let (rect, i, rotated) = best.ok_or(())?;
+            skyline_insert(&mut skyline, i, rect.x, rect.bottom(), rect.w);
+            placed.push((
+                rect,
+                Pic {
+                    rotated,
+                    ..item.data.clone()
+                },
+            ));
+        }
+        Ok(placed)
+    }
+}
+
+//Lowest y where item of width `w` can stand starting from segment `i`
+fn skyline_fit(skyline: &[Segment], i: usize, w: usize, area: &Rect) -> Option<usize> {
+    if skyline[i].x + w > area.right() {
+        return None;
+    }
+    let mut width_left = w;
+    let mut y = 0;
+    for segment in &skyline[i..] {
+        y = y.max(segment.y);
+        if segment.w >= width_left {
+            return Some(y);
+        }
+        width_left -= segment.w;
+    }
+    None
+}
+
+fn skyline_insert(skyline: &mut Vec<Segment>, i: usize, x: usize, y: usize, w: usize) {
+    skyline.insert(i, Segment { x, y, w });
+    let right = x + w;
+    while let Some(next) = skyline.get_mut(i + 1) {
+        if next.x >= right {
+            break;
+        }
+        let shrink = right - next.x;
+        if next.w <= shrink {
+            skyline.remove(i + 1);
+        } else {
+            next.x += shrink;
+            next.w -= shrink;
+            break;
+        }
+    }
+    //Merge neighbours of equal height
+    let mut j = 0;
+    while j + 1 < skyline.len() {
+        if skyline[j].y == skyline[j + 1].y {
+            skyline[j].w += skyline[j + 1].w;
+            skyline.remove(j + 1);
+        } else {
+            j += 1;
+        }
+    }
+}
+
+/// First Fit shelf packer. Items are put in rows left to right.
+pub struct ShelfEngine;
+
+struct Shelf {
+    y: usize,
+    h: usize,
+    used_w: usize,
+}
+
+impl LayoutEngine for ShelfEngine {
+    fn pack(&self, area: Rect, items: &[Item<Pic>]) -> Result<Positions, ()> {
+        let mut shelves = Vec::<Shelf>::new();
+        let mut placed = Positions::with_capacity(items.len());
+        for item in items {
+            //Tallest orientation that fits existing shelf, or the flattest one for a new shelf
+            let fit = shelves.iter().enumerate().find_map(|(i, shelf)| {
+                orientations(item)
+                    .filter(|(w, h, _)| *h <= shelf.h && shelf.used_w + w <= area.w)
+                    .max_by_key(|(_, h, _)| *h)
+                    .map(|(w, h, rotated)| (i, w, h, rotated))
+            });
+            let (i, w, h, rotated) = match fit {
+                Some(fit) => fit,
+                None => {
+                    let y = shelves.last().map_or(area.y, |last| last.y + last.h);
+                    let (w, h, rotated) = orientations(item)
+                        .filter(|(w, h, _)| *w <= area.w && y + h <= area.bottom())
+                        .min_by_key(|(_, h, _)| *h)
+                        .ok_or(())?;
+                    shelves.push(Shelf { y, h, used_w: 0 });
+                    (shelves.len() - 1, w, h, rotated)
+                }
+            };
+            let shelf = &mut shelves[i];
+            placed.push((
+                Rect::new(area.x + shelf.used_w, shelf.y, w, h),
+                Pic {
+                    rotated,
+                    ..item.data.clone()
+                },
+            ));
+            shelf.used_w += w;
+        }
+        Ok(placed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{assert_apart, test_items};
+
+    #[test]
+    fn placements_stay_inside_area_and_apart() {
+        let area = Rect::new(3, 5, 200, 200);
+        for algorithm in PackingAlgorithm::ALL {
+            let positions = algorithm
+                .engine()
+                .pack(area, &test_items(40, Rotation::None))
+                .unwrap();
+            assert_eq!(positions.len(), 40, "{}", algorithm.name());
+            assert_apart(area, &positions);
+        }
+    }
+
+    #[test]
+    fn pack_some_places_longest_fitting_run() {
+        let items: Vec<Item<Pic>> = (0..10)
+            .map(|_| Item::new(Pic::blank(10, 10), 10, 10, Rotation::None))
+            .collect();
+        for algorithm in PackingAlgorithm::ALL {
+            let (positions, count) = algorithm.engine().pack_some(Rect::of_size(20, 20), &items);
+            assert_eq!(count, 4, "{}", algorithm.name());
+            assert_eq!(positions.len(), 4);
+        }
+    }
+
+    #[test]
+    fn free_rects_avoid_used_space() {
+        let area = Rect::of_size(100, 100);
+        let used = [
+            Rect::new(10, 10, 30, 20),
+            Rect::new(50, 0, 50, 50),
+            Rect::new(0, 60, 40, 40),
+        ];
+        let mut free = vec![area];
+        for rect in &used {
+            split_free_rects(&mut free, rect);
+        }
+        assert!(!free.is_empty());
+        for (i, rect) in free.iter().enumerate() {
+            assert!(area.contains(rect));
+            assert!(used.iter().all(|u| !rect.overlaps(u)));
+            assert!(free
+                .iter()
+                .enumerate()
+                .all(|(j, other)| i == j || !other.contains(rect)));
+        }
+    }
+
+    #[test]
+    fn skyline_stays_contiguous() {
+        let mut skyline = vec![Segment { x: 0, y: 0, w: 100 }];
+        skyline_insert(&mut skyline, 0, 0, 30, 40);
+        skyline_insert(&mut skyline, 1, 40, 10, 25);
+        skyline_insert(&mut skyline, 1, 40, 30, 50);
+        let mut x = 0;
+        for segment in &skyline {
+            assert_eq!(segment.x, x);
+            x += segment.w;
+        }
+        assert_eq!(x, 100);
+        assert!(skyline.windows(2).all(|pair| pair[0].y != pair[1].y));
+    }
+
+    #[test]
+    fn rotated_placements_stay_apart() {
+        let area = Rect::new(3, 5, 200, 200);
+        for algorithm in PackingAlgorithm::ALL {
+            let positions = algorithm
+                .engine()
+                .pack(area, &test_items(40, Rotation::Allowed))
+                .unwrap();
+            assert_eq!(positions.len(), 40, "{}", algorithm.name());
+            assert_apart(area, &positions);
+        }
+    }
+
+    #[test]
+    fn rotation_is_flagged_only_for_swapped_sides() {
+        let area = Rect::of_size(200, 200);
+        for algorithm in PackingAlgorithm::ALL {
+            for rotation in [Rotation::None, Rotation::Allowed] {
+                let positions = algorithm
+                    .engine()
+                    .pack(area, &test_items(40, rotation))
+                    .unwrap();
+                for (rect, pic) in positions {
+                    let (w, h) = (pic.width as usize, pic.height as usize);
+                    match pic.rotated {
+                        true => {
+                            assert!(rotation == Rotation::Allowed, "{}", algorithm.name());
+                            assert_ne!(w, h);
+                            assert_eq!((rect.w, rect.h), (h, w), "{}", algorithm.name());
+                        }
+                        false => assert_eq!((rect.w, rect.h), (w, h), "{}", algorithm.name()),
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn square_cell_of_oblong_image_is_not_rotated() {
+        //Aligned cell of 100x99 image is square
+        let item = Item::new(Pic::blank(100, 99), 100, 100, Rotation::None);
+        for algorithm in PackingAlgorithm::ALL {
+            let positions = algorithm
+                .engine()
+                .pack(Rect::of_size(100, 100), std::slice::from_ref(&item))
+                .unwrap();
+            assert!(!positions[0].1.rotated, "{}", algorithm.name());
+        }
+    }
+}
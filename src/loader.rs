@@ -1,19 +1,257 @@
-use super::utils::random_gray;
-use crunch::{Item, Rotation};
+use super::tonemapping::ToneMapSettings;
+use super::utils::{random_gray, RectSize};
+use crunch::{Item, Rect, Rotation};
 use eframe::egui::DroppedFile;
-use image::{io::Reader, ImageResult};
-use std::fs;
+use exr::meta::MetaData;
+use exr::prelude::read_first_rgba_layer_from_file;
+use image::codecs::{gif::GifDecoder, hdr::HdrDecoder, png::PngDecoder};
+use image::error::{DecodingError, ImageFormatHint};
+use image::{
+    io::Reader, AnimationDecoder, DynamicImage, Frames, GenericImageView, ImageError, ImageFormat,
+    ImageResult,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::BufReader;
 use std::path::{Path, PathBuf};
+
+/// Position of image in animation it was taken from, `delay` is in milliseconds.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AnimationFrame {
+    pub index: usize,
+    pub delay: u32,
+}
+
 #[derive(Clone)]
 pub struct Pic {
     pub file: PathBuf,
+    /// Size of file in bytes, read once on loading.
+    pub file_size: u64,
+    pub original: RectSize,
     pub width: u32,
     pub height: u32,
     pub color: image::Rgba<u8>,
+    pub crop: Option<Rect>,
+    pub priority: f32,
+    pub rotated: bool,
+    pub animation: Option<AnimationFrame>,
+}
+impl Pic {
+    /// Part of source image that is shown, in source pixels.
+    pub fn region(&self) -> Rect {
+        self.crop
+            .unwrap_or_else(|| Rect::of_size(self.original.w, self.original.h))
+    }
+}
+
+#[cfg(test)]
+impl Pic {
+    /// Picture of given size without file, for checks of layouts.
+    pub fn blank(width: u32, height: u32) -> Self {
+        Pic {
+            file: PathBuf::new(),
+            file_size: 0,
+            original: RectSize::new(width as usize, height as usize),
+            width,
+            height,
+            color: image::Rgba([0, 0, 0, 255]),
+            crop: None,
+            priority: 1.0,
+            rotated: false,
+            animation: None,
+        }
+    }
+}
+
+/// Blank items of varied sizes from 5x4 to 35x26, same for every run.
+#[cfg(test)]
+pub fn test_items(num: usize, rotation: Rotation) -> Vec<Item<Pic>> {
+    (0..num)
+        .map(|i| {
+            let (w, h) = (5 + i * 7 % 31, 4 + i * 13 % 23);
+            Item::new(Pic::blank(w as u32, h as u32), w, h, rotation)
+        })
+        .collect()
+}
+
+/// Fails if any of `positions` leaves `area` or overlaps another one.
+#[cfg(test)]
+pub fn assert_apart(area: Rect, positions: &[(Rect, Pic)]) {
+    for (i, (rect, _)) in positions.iter().enumerate() {
+        assert!(area.contains(rect), "{:?} is outside of {:?}", rect, area);
+        for (other, _) in &positions[i + 1..] {
+            assert!(!rect.overlaps(other), "{:?} overlaps {:?}", rect, other);
+        }
+    }
+}
+
+/// Smallest rectangle that holds all pixels that are not fully transparent.
+pub fn opaque_bounds(image: &DynamicImage) -> Option<Rect> {
+    //Faint alpha of 16-bit images doesn't round down to zero
+    let image = image.to_rgba16();
+    let (mut left, mut top, mut right, mut bottom) = (u32::MAX, u32::MAX, 0, 0);
+    for (x, y, pixel) in image.enumerate_pixels() {
+        if pixel[3] > 0 {
+            left = left.min(x);
+            top = top.min(y);
+            right = right.max(x + 1);
+            bottom = bottom.max(y + 1);
+        }
+    }
+    match right > left && bottom > top {
+        true => Some(Rect::new(
+            left as usize,
+            top as usize,
+            (right - left) as usize,
+            (bottom - top) as usize,
+        )),
+        false => None,
+    }
+}
+
+//OpenEXR keeps color premultiplied by alpha, tone mapping expects straight one
+fn unpremultiplied([r, g, b, a]: [f32; 4]) -> [f32; 4] {
+    match a > 0.0 {
+        true => [r / a, g / a, b / a, a],
+        false => [r, g, b, a],
+    }
+}
+
+/// Formats that hold light brighter than white, they are tone mapped on opening.
+#[derive(PartialEq, Debug, Clone, Copy)]
+enum HighDynamicRange {
+    Radiance,
+    OpenExr,
+}
+
+fn high_dynamic_range(file: &Path) -> Option<HighDynamicRange> {
+    match file.extension()?.to_str()?.to_lowercase().as_str() {
+        "hdr" => Some(HighDynamicRange::Radiance),
+        "exr" => Some(HighDynamicRange::OpenExr),
+        _ => None,
+    }
+}
+
+/// Look of HDR image depends on tone mapping, so it is opened again when that changes.
+pub fn is_high_dynamic_range(file: &Path) -> bool {
+    high_dynamic_range(file).is_some()
+}
+
+/// Opens whole image, frames of animated ones are opened by `open_animation`.
+/// HDR images come tone mapped to 16 bits per channel.
+pub fn open_image(file: &Path, tone_mapping: &ToneMapSettings) -> ImageResult<DynamicImage> {
+    match high_dynamic_range(file) {
+        Some(format) => open_high_dynamic_range(file, format, tone_mapping),
+        None => image::open(file),
+    }
+}
+
+//Decoding stops at the second frame
+fn is_animated(file: &Path) -> bool {
+    animation_frames(file)
+        .ok()
+        .flatten()
+        .is_some_and(|frames| frames.take(2).count() > 1)
 }
 
+/// All frames of animated GIF or APNG with their delays, `None` for still images.
+pub fn open_animation(file: &Path) -> ImageResult<Option<Vec<(DynamicImage, u32)>>> {
+    let frames = match animation_frames(file)? {
+        Some(frames) => frames.collect_frames()?,
+        None => return Ok(None),
+    };
+    Ok((frames.len() > 1).then(|| {
+        frames
+            .into_iter()
+            .map(|frame| {
+                let (numer, denom) = frame.delay().numer_denom_ms();
+                (
+                    DynamicImage::ImageRgba8(frame.into_buffer()),
+                    numer / denom.max(1),
+                )
+            })
+            .collect()
+    }))
+}
+
+fn open_high_dynamic_range(
+    file: &Path,
+    format: HighDynamicRange,
+    tone_mapping: &ToneMapSettings,
+) -> ImageResult<DynamicImage> {
+    let (w, h, pixels) = match format {
+        HighDynamicRange::Radiance => {
+            let decoder = HdrDecoder::new(BufReader::new(File::open(file)?))?;
+            let metadata = decoder.metadata();
+            let pixels: Vec<[f32; 4]> = decoder
+                .read_image_hdr()?
+                .iter()
+                .map(|pixel| [pixel[0], pixel[1], pixel[2], 1.0])
+                .collect();
+            (metadata.width, metadata.height, pixels)
+        }
+        HighDynamicRange::OpenExr => {
+            let image = read_first_rgba_layer_from_file(
+                file,
+                |size, _| vec![vec![[0.0, 0.0, 0.0, 1.0]; size.width()]; size.height()],
+                |rows: &mut Vec<Vec<[f32; 4]>>, position, (r, g, b, a): (f32, f32, f32, f32)| {
+                    rows[position.y()][position.x()] = unpremultiplied([r, g, b, a])
+                },
+            )
+            .map_err(exr_error)?;
+            let size = image.layer_data.size;
+            (
+                size.width() as u32,
+                size.height() as u32,
+                image.layer_data.channel_data.pixels.concat(),
+            )
+        }
+    };
+    Ok(DynamicImage::ImageRgba16(tone_mapping.apply(w, h, &pixels)))
+}
+
+fn exr_error(err: exr::error::Error) -> ImageError {
+    ImageError::Decoding(DecodingError::new(
+        ImageFormatHint::Name("OpenEXR".to_string()),
+        err,
+    ))
+}
+
+//Frames come composed to full canvas
+fn animation_frames(file: &Path) -> ImageResult<Option<Frames<'static>>> {
+    let format = Reader::open(file)?.with_guessed_format()?.format();
+    let reader = BufReader::new(File::open(file)?);
+    match format {
+        Some(ImageFormat::Gif) => Ok(Some(GifDecoder::new(reader)?.into_frames())),
+        Some(ImageFormat::Png) => {
+            let decoder = PngDecoder::new(reader)?;
+            match decoder.is_apng() {
+                true => Ok(Some(decoder.apng().into_frames())),
+                false => Ok(None),
+            }
+        }
+        _ => Ok(None),
+    }
+}
+
+//OpenEXR is read by its own crate, header holds size of first layer
 fn get_dimensions(path: &Path) -> ImageResult<(u32, u32)> {
-    Reader::open(path)?.with_guessed_format()?.into_dimensions()
+    match high_dynamic_range(path) {
+        Some(HighDynamicRange::OpenExr) => {
+            let metadata = MetaData::read_from_file(path, false).map_err(exr_error)?;
+            Ok(metadata
+                .headers
+                .first()
+                .map(|header| {
+                    (
+                        header.layer_size.width() as u32,
+                        header.layer_size.height() as u32,
+                    )
+                })
+                .unwrap_or_default())
+        }
+        _ => Reader::open(path)?.with_guessed_format()?.into_dimensions(),
+    }
 }
 
 pub fn get_all_files(path: &Path) -> Vec<PathBuf> {
@@ -32,7 +270,8 @@ pub fn get_all_files(path: &Path) -> Vec<PathBuf> {
     result
 }
 
-pub fn load_new_items(dropped_items: &[DroppedFile]) -> Vec<Item<Pic>> {
+/// With `split_animations` every frame of animated image becomes separate item.
+pub fn load_new_items(dropped_items: &[DroppedFile], split_animations: bool) -> Vec<Item<Pic>> {
     let mut all_files = Vec::<PathBuf>::new();
     let mut new_items = Vec::<Item<Pic>>::new();
     for dropped in dropped_items {
@@ -41,14 +280,49 @@ pub fn load_new_items(dropped_items: &[DroppedFile]) -> Vec<Item<Pic>> {
         }
     }
     for file in all_files {
+        let file_size = fs::metadata(&file).map_or(0, |metadata| metadata.len());
+        if split_animations && is_animated(&file) {
+            if let Ok(Some(frames)) = open_animation(&file) {
+                for (index, (frame, delay)) in frames.iter().enumerate() {
+                    let (w, h) = (frame.width(), frame.height());
+                    new_items.push(Item::new(
+                        Pic {
+                            file: file.to_owned(),
+                            file_size,
+                            original: RectSize::new(w as usize, h as usize),
+                            width: w,
+                            height: h,
+                            color: random_gray(),
+                            crop: None,
+                            priority: 1.0,
+                            rotated: false,
+                            animation: Some(AnimationFrame {
+                                index,
+                                delay: *delay,
+                            }),
+                        },
+                        w as usize,
+                        h as usize,
+                        Rotation::None,
+                    ));
+                }
+                continue;
+            }
+        }
         if let Ok(dimensions) = get_dimensions(&file) {
             if dimensions.0 > 0 && dimensions.1 > 0 {
                 new_items.push(Item::new(
                     Pic {
                         file: file.to_owned(),
+                        file_size,
+                        original: RectSize::new(dimensions.0 as usize, dimensions.1 as usize),
                         width: dimensions.0,
                         height: dimensions.1,
                         color: random_gray(),
+                        crop: None,
+                        priority: 1.0,
+                        rotated: false,
+                        animation: None,
                     },
                     dimensions.0 as usize,
                     dimensions.1 as usize,
@@ -59,3 +333,83 @@ pub fn load_new_items(dropped_items: &[DroppedFile]) -> Vec<Item<Pic>> {
     }
     new_items
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::gif::GifEncoder;
+    use image::{Delay, Frame};
+    use image::{ImageBuffer, Rgba, RgbaImage};
+
+    #[test]
+    fn padded_sprite_is_trimmed_to_opaque_pixels() {
+        let image = RgbaImage::from_fn(10, 8, |x, y| {
+            match (2..7).contains(&x) && (3..5).contains(&y) {
+                true => Rgba([255, 0, 0, 255]),
+                false => Rgba([255, 0, 0, 0]),
+            }
+        });
+        let bounds = opaque_bounds(&DynamicImage::ImageRgba8(image));
+        assert_eq!(bounds, Some(Rect::new(2, 3, 5, 2)));
+    }
+
+    #[test]
+    fn transparent_image_has_no_bounds() {
+        let image = RgbaImage::from_pixel(6, 6, Rgba([40, 40, 40, 0]));
+        assert_eq!(opaque_bounds(&DynamicImage::ImageRgba8(image)), None);
+    }
+
+    #[test]
+    fn faint_sixteen_bit_alpha_is_kept() {
+        let image = ImageBuffer::from_fn(6, 6, |x, y| match (x, y) {
+            (4, 1) => Rgba([0u16, 0, 0, 100]),
+            _ => Rgba([0u16, 0, 0, 0]),
+        });
+        let bounds = opaque_bounds(&DynamicImage::ImageRgba16(image));
+        assert_eq!(bounds, Some(Rect::new(4, 1, 1, 1)));
+    }
+
+    fn write_gif(path: &Path, frames: usize) {
+        let mut encoder = GifEncoder::new(File::create(path).unwrap());
+        for i in 0..frames {
+            let image = RgbaImage::from_pixel(4, 3, Rgba([i as u8 * 60, 0, 0, 255]));
+            let delay = Delay::from_numer_denom_ms(100, 1);
+            encoder
+                .encode_frame(Frame::from_parts(image, 0, 0, delay))
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn animated_gifs_are_split_to_frames() {
+        let folder = std::env::temp_dir().join("ppp_animated");
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        write_gif(&folder.join("walk.gif"), 3);
+        write_gif(&folder.join("still.gif"), 1);
+        assert!(is_animated(&folder.join("walk.gif")));
+        assert!(!is_animated(&folder.join("still.gif")));
+
+        let dropped = DroppedFile {
+            path: Some(folder),
+            name: String::new(),
+            last_modified: None,
+            bytes: None,
+        };
+        let items = load_new_items(&[dropped], true);
+        let frames: Vec<usize> = items
+            .iter()
+            .filter_map(|item| item.data.animation.map(|frame| frame.index))
+            .collect();
+        assert_eq!(items.len(), 4);
+        assert_eq!(frames, [0, 1, 2]);
+    }
+
+    #[test]
+    fn exr_color_is_unpremultiplied() {
+        assert_eq!(unpremultiplied([0.25, 0.5, 2.0, 0.5]), [0.5, 1.0, 4.0, 0.5]);
+        assert_eq!(unpremultiplied([0.3, 0.6, 0.9, 1.0]), [0.3, 0.6, 0.9, 1.0]);
+        //Fully transparent pixels keep their color
+        assert_eq!(unpremultiplied([0.1, 0.0, 0.0, 0.0]), [0.1, 0.0, 0.0, 0.0]);
+    }
+}
@@ -1,24 +1,73 @@
 #![windows_subsystem = "windows"]
 
 mod app;
+mod channels;
+mod layout;
 mod loader;
+mod metadata;
+mod modes;
+mod optimizer;
+mod output;
 mod packer;
+mod resampling;
+mod sheets;
+mod tonemapping;
+mod unpacker;
 mod utils;
 
 use app::*;
-use clap::{App, Arg};
+use channels::ChannelSettings;
+use clap::{App, Arg, SubCommand};
 use eframe::egui::DroppedFile;
-use packer::Packer;
+use image::ImageError;
+use layout::PackingAlgorithm;
+use metadata::LayoutMetadata;
+use modes::{GridSettings, LayoutMode, MasonrySettings, TreemapSettings, TreemapWeight};
+use optimizer::OptimizerSettings;
+use output::{OutputFormat, OutputSettings, PngCompression};
+use packer::{Packer, PageSettings};
+use resampling::{Resampling, ResamplingSettings};
+use sheets::{SheetFormat, StyleSheet};
 use std::path::PathBuf;
+use tonemapping::{ToneMapSettings, ToneMapping};
+use unpacker::{slice, unpack};
 use utils::*;
 
 struct CLIArgsParsed {
-    pub input: PathBuf,
+    pub input: Option<PathBuf>,
+    pub layout: Option<PathBuf>,
+    pub save_layout: Option<PathBuf>,
     pub output: PathBuf,
     pub ratio: AspectRatio,
+    pub mode: LayoutMode,
+    pub grid: GridSettings,
+    pub masonry: MasonrySettings,
+    pub treemap: TreemapSettings,
+    pub priorities: Vec<(PathBuf, f32)>,
+    pub algorithm: PackingAlgorithm,
     pub equal: bool,
+    pub trim: bool,
+    pub split_frames: bool,
+    pub margin: usize,
+    pub extrude: usize,
+    pub align: usize,
+    pub channels: ChannelSettings,
+    pub rotate: bool,
     pub scale: ImageScaling,
     pub zip: bool,
+    pub json: bool,
+    pub gif: bool,
+    pub sheet: Option<SheetFormat>,
+    pub style: Option<StyleSheet>,
+    pub format: OutputSettings,
+    pub resampling: ResamplingSettings,
+    pub pixel_art: bool,
+    pub blend: bool,
+    pub sixteen_bit: bool,
+    pub tone_mapping: ToneMapSettings,
+    pub iterations: Option<usize>,
+    pub optimizer: OptimizerSettings,
+    pub paging: PageSettings,
 }
 
 fn run_cli(args: CLIArgsParsed) {
@@ -26,15 +75,107 @@ fn run_cli(args: CLIArgsParsed) {
         ImageScaling::Preview(side) => side,
         _ => 512.0,
     };
-    let mut packer = Packer::new(size, args.ratio, args.scale, args.equal);
-    let dropped = DroppedFile {
-        path: Some(args.input),
-        name: String::new(),
-        last_modified: None,
-        bytes: None,
+    let mut packer = Packer::new(size, args.ratio, args.algorithm, args.scale, args.equal);
+    packer.mode = args.mode;
+    if let Some(iterations) = args.iterations {
+        packer.max_iterations = iterations;
+    }
+    packer.rotate = args.rotate;
+    packer.trim = args.trim;
+    packer.split_frames = args.split_frames;
+    packer.margin = args.margin;
+    packer.extrude = args.extrude;
+    packer.align = args.align;
+    packer.channels = args.channels;
+    packer.output = args.format;
+    packer.resampling = args.resampling;
+    packer.pixel_art = args.pixel_art;
+    if args.pixel_art && args.mode != LayoutMode::Packing {
+        println!("Pixel art works only in packing mode!");
+    }
+    packer.blend = args.blend;
+    packer.sixteen_bit = args.sixteen_bit;
+    packer.tone_mapping = args.tone_mapping;
+    packer.optimizer = args.optimizer;
+    packer.paging = args.paging;
+    if packer.paged() && args.scale != ImageScaling::Actual {
+        println!("Pages are exported in actual size, export size is ignored!");
+    }
+    packer.grid = args.grid;
+    packer.masonry = args.masonry;
+    packer.treemap = args.treemap;
+    match &args.layout {
+        Some(layout) => {
+            if let Err(err) = packer.load_layout(layout) {
+                println!("Couldn't load layout!\n{}", err);
+                return;
+            }
+        }
+        None => {
+            let dropped = DroppedFile {
+                path: args.input.clone(),
+                name: String::new(),
+                last_modified: None,
+                bytes: None,
+            };
+            packer.update(&[dropped]);
+            if !args.priorities.is_empty() {
+                for (file, priority) in &args.priorities {
+                    packer.set_priority(file, *priority);
+                }
+                packer.update(&[]);
+            }
+        }
+    }
+    if packer.pixel_art_overflows() {
+        println!("Pixel art is exported in actual size, it is larger than export size!");
+    }
+    if let Some(path) = &args.save_layout {
+        match packer.save_layout(path) {
+            Ok(_) => println!("Layout file saved!"),
+            Err(err) => println!("Couldn't save layout file!\n{}", err),
+        }
+    }
+    packer.export(
+        &args.output,
+        args.zip,
+        args.json,
+        args.gif,
+        args.sheet,
+        args.style,
+    );
+}
+
+pub struct UnpackArgsParsed {
+    pub image: Option<PathBuf>,
+    pub metadata: Option<PathBuf>,
+    pub output: PathBuf,
+    pub columns: Option<u32>,
+    pub rows: Option<u32>,
+}
+
+fn run_unpack(args: UnpackArgsParsed) {
+    if let Err(err) = std::fs::create_dir_all(&args.output) {
+        println!("Couldn't create output directory!\n{}", err);
+        return;
+    }
+    let result = match (&args.metadata, &args.image) {
+        (Some(metadata), image) => LayoutMetadata::open(metadata)
+            .map_err(ImageError::from)
+            .and_then(|layout| {
+                //Atlas is next to its metadata unless given
+                let image = image
+                    .clone()
+                    .unwrap_or_else(|| metadata.with_file_name(&layout.image));
+                unpack(&image, &layout, &args.output)
+            }),
+        (None, Some(image)) => slice(image, args.columns, args.rows, &args.output),
+        (None, None) => return,
     };
-    packer.update(&[dropped]);
-    packer.export(&args.output, args.zip);
+    match result {
+        Ok(saved) => println!("{} images saved!", saved),
+        Err(err) => println!("Couldn't unpack image!\n{}", err),
+    }
 }
 
 fn run_gui() {
@@ -74,6 +215,21 @@ fn main() {
                 .help("Sets directory to get images from.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("layout")
+                .short("l")
+                .long("layout")
+                .value_name("LAYOUT_FILE")
+                .help("Renders layout saved before instead of packing images from input.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("save_layout")
+                .long("save-layout")
+                .value_name("LAYOUT_FILE")
+                .help("Saves layout to file, to render it again later.")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("output")
                 .short("o")
@@ -90,6 +246,77 @@ fn main() {
                 .help("Sets aspect ratio of package. Can be a float or a pair of integers. Default is 1:1.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("mode")
+                .short("m")
+                .long("mode")
+                .value_name("MODE")
+                .help("Sets layout mode: packing, justified, grid, masonry, treemap or frames. Default is packing.")
+                .possible_values(&["packing", "justified", "grid", "masonry", "treemap", "frames"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("columns")
+                .long("columns")
+                .value_name("COLUMNS")
+                .help("Sets number of columns in grid, masonry and frames modes. Default is automatic.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rows")
+                .long("rows")
+                .value_name("ROWS")
+                .help("Sets number of rows in grid and frames modes. Default is automatic.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("fill")
+                .long("fill")
+                .value_name("FILL")
+                .help("Crop images to fill grid cells instead of fitting them.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("weight")
+                .short("w")
+                .long("weight")
+                .value_name("WEIGHT")
+                .help("Sets cell weight in treemap mode: filesize, pixels or priority. Default is filesize.")
+                .possible_values(&["filesize", "pixels", "priority"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("priority")
+                .short("p")
+                .long("priority")
+                .value_name("FILE:PRIORITY")
+                .help("Sets priority of image for treemap mode. Default is 1.")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("algorithm")
+                .short("a")
+                .long("algorithm")
+                .value_name("ALGORITHM")
+                .help("Sets packing algorithm: crunch, maxrects, skyline or shelf. Default is crunch.")
+                .possible_values(&["crunch", "maxrects", "skyline", "shelf"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("rotate")
+                .long("rotate")
+                .value_name("ROTATE")
+                .help("Allow packing algorithm to rotate images by 90 degrees.")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("equal")
                 .short("e")
@@ -98,6 +325,47 @@ fn main() {
                 .help("Scale images to equal size.")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("trim")
+                .short("t")
+                .long("trim")
+                .help("Cut off transparent borders of images before packing.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("split_frames")
+                .long("split-frames")
+                .help("Add frames of animated GIF and APNG images as separate images.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("margin")
+                .long("margin")
+                .value_name("PIXELS")
+                .help("Sets space between images. Default is 0.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("extrude")
+                .long("extrude")
+                .value_name("PIXELS")
+                .help("Repeats edge pixels of images into margin. Default is 0.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("align")
+                .long("align")
+                .value_name("PIXELS")
+                .help("Places packed images at multiples of 4, 8 or 16 pixels. Default is 0, no alignment.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("channels")
+                .long("channels")
+                .value_name("SUFFIXES")
+                .help("Packs images once and renders the same layout for companions with given suffixes, like \"_n,_r,_ao\".")
+                .takes_value(true),
+        )
         .arg(
             Arg::with_name("size")
                 .short("s")
@@ -106,6 +374,139 @@ fn main() {
                 .help("Sets maximum dimension of exported image. Default is Actual.")
                 .takes_value(true),
         )
+        .arg(
+            Arg::with_name("iterations")
+                .long("iterations")
+                .value_name("ITERATIONS")
+                .help("Sets maximum number of attempts to fit images. Default is 32.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("optimize")
+                .long("optimize")
+                .value_name("ATTEMPTS")
+                .help("Try packing images in several orders and keep the densest result. Doesn't affect crunch.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("time")
+                .long("time")
+                .value_name("SECONDS")
+                .help("Sets time limit of optimization, 0 is unlimited. Default is 2.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Sets seed for random orders of optimization. Default is 0.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("max_size")
+                .long("max-size")
+                .value_name("SIZE")
+                .help("Sets maximum side of page, images that don't fit go to extra pages. Pages are exported in actual size. Default is 0, no limit.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("po2")
+                .long("po2")
+                .help("Round sides of pages up to power of two, maximum side is rounded down to it.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("filter")
+                .long("filter")
+                .value_name("FILTER")
+                .help("Sets resampling filter: nearest, triangle, catmullrom, gaussian or lanczos3. Default is catmullrom.")
+                .possible_values(&["nearest", "triangle", "catmullrom", "catmull-rom", "gaussian", "lanczos3"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("linear")
+                .long("linear")
+                .help("Resize images in linear light, so they keep brightness.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("pixel_art")
+                .long("pixel-art")
+                .help("Scale images by whole numbers only and never blend their pixels. Works in packing mode, images are never shrunk below actual size.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("blend")
+                .long("blend")
+                .help("Lay transparent images over ones under them instead of replacing pixels.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("tone_map")
+                .long("tone-map")
+                .value_name("CURVE")
+                .help("Sets tone mapping of HDR and EXR images: clamp, reinhard or aces. Default is clamp.")
+                .possible_values(&["clamp", "reinhard", "aces", "acesfilmic", "aces-filmic"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("exposure")
+                .long("exposure")
+                .value_name("STOPS")
+                .help("Sets exposure of HDR and EXR images in stops before tone mapping. Default is 0.")
+                .allow_hyphen_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("format")
+                .long("format")
+                .value_name("FORMAT")
+                .help("Sets format of combined image: png, jpeg, webp, tiff, bmp or tga. Default is png.")
+                .possible_values(&["png", "jpeg", "jpg", "webp", "tiff", "tif", "bmp", "tga"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("sixteen_bit")
+                .long("16-bit")
+                .help("Keep 16 bits per channel of deep images. Works with png and tiff formats.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("compression")
+                .long("compression")
+                .value_name("LEVEL")
+                .help("Sets PNG compression: fast, default or best. Default is default.")
+                .possible_values(&["fast", "default", "best"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quality")
+                .long("quality")
+                .value_name("QUALITY")
+                .help("Sets JPEG quality from 1 to 100. Default is 90.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("background")
+                .long("background")
+                .value_name("RRGGBB")
+                .help("Sets color that JPEG is flattened over. Default is FFFFFF.")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("no_flatten")
+                .long("no-flatten")
+                .help("Drop transparency of JPEG without laying images over background.")
+                .takes_value(false),
+        )
         .arg(
             Arg::with_name("zip")
                 .short("z")
@@ -114,11 +515,109 @@ fn main() {
                 .help("Also pack all given images to ZIP archive.")
                 .takes_value(false),
         )
+        .arg(
+            Arg::with_name("json")
+                .short("j")
+                .long("json")
+                .help("Also save canvas size and positions of images to JSON file.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("gif")
+                .long("gif")
+                .help("Also save animated GIF where animated images play and others are held.")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::with_name("sheet")
+                .long("sheet")
+                .value_name("FORMAT")
+                .help("Also save sprite sheet descriptor: JSON-Hash, JSON-Array, Sparrow, Godot or Unity.")
+                .possible_values(&["JSON-Hash", "JSON-Array", "Sparrow", "Godot", "Unity"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("style")
+                .long("style")
+                .value_name("FORMAT")
+                .help("Also save stylesheet with class for each image: CSS or SCSS.")
+                .possible_values(&["CSS", "SCSS"])
+                .case_insensitive(true)
+                .hide_possible_values(true)
+                .takes_value(true),
+        )
+        .subcommand(
+            SubCommand::with_name("unpack")
+                .about("Cuts combined image back to separate images.")
+                .arg(
+                    Arg::with_name("image")
+                        .short("i")
+                        .long("image")
+                        .value_name("IMAGE")
+                        .help("Sets combined image. Default is the one named in metadata.")
+                        .required_unless("metadata")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("metadata")
+                        .short("m")
+                        .long("metadata")
+                        .value_name("JSON")
+                        .help("Sets layout metadata saved with combined image. Without it image is cut to grid.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("output")
+                        .short("o")
+                        .long("output")
+                        .value_name("OUTPUT_DIR")
+                        .help("Sets directory to put images. Default is Pictures folder.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("columns")
+                        .long("columns")
+                        .value_name("COLUMNS")
+                        .help("Sets number of grid columns. Cells are square if rows are not set.")
+                        .takes_value(true),
+                )
+                .arg(
+                    Arg::with_name("rows")
+                        .long("rows")
+                        .value_name("ROWS")
+                        .help("Sets number of grid rows. Cells are square if columns are not set.")
+                        .takes_value(true),
+                ),
+        )
         .get_matches();
 
-    if let Some(dir) = cli.value_of("input") {
+    if let Some(unpack) = cli.subcommand_matches("unpack") {
+        run_unpack(UnpackArgsParsed {
+            image: unpack.value_of("image").map(PathBuf::from),
+            metadata: unpack.value_of("metadata").map(PathBuf::from),
+            output: match unpack.value_of("output") {
+                Some(out) => PathBuf::from(out),
+                None => default_path(),
+            },
+            columns: unpack
+                .value_of("columns")
+                .and_then(|n| n.parse::<u32>().ok()),
+            rows: unpack.value_of("rows").and_then(|n| n.parse::<u32>().ok()),
+        });
+        return;
+    }
+
+    if cli.is_present("input") || cli.is_present("layout") {
+        let columns = cli
+            .value_of("columns")
+            .and_then(|n| n.parse::<usize>().ok())
+            .unwrap_or(0);
         run_cli(CLIArgsParsed {
-            input: PathBuf::from(dir),
+            input: cli.value_of("input").map(PathBuf::from),
+            layout: cli.value_of("layout").map(PathBuf::from),
+            save_layout: cli.value_of("save_layout").map(PathBuf::from),
             output: match cli.value_of("output") {
                 Some(out) => PathBuf::from(out),
                 None => default_path(),
@@ -127,7 +626,63 @@ fn main() {
                 Some(ratio) => AspectRatio::Custom(parse_custom_ratio(ratio)),
                 None => AspectRatio::Square,
             },
+            mode: cli
+                .value_of("mode")
+                .and_then(LayoutMode::from_name)
+                .unwrap_or_default(),
+            grid: GridSettings {
+                columns,
+                rows: cli
+                    .value_of("rows")
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or(0),
+                fill: cli.is_present("fill"),
+            },
+            masonry: MasonrySettings { columns },
+            treemap: TreemapSettings {
+                weight: cli
+                    .value_of("weight")
+                    .and_then(TreemapWeight::from_name)
+                    .unwrap_or_default(),
+            },
+            priorities: cli
+                .values_of("priority")
+                .map(|values| {
+                    values
+                        .filter_map(|value| value.rsplit_once(':'))
+                        .filter_map(|(file, priority)| {
+                            Some((PathBuf::from(file), priority.parse::<f32>().ok()?))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default(),
+            algorithm: cli
+                .value_of("algorithm")
+                .and_then(PackingAlgorithm::from_name)
+                .unwrap_or_default(),
             equal: cli.is_present("equal"),
+            trim: cli.is_present("trim"),
+            split_frames: cli.is_present("split_frames"),
+            margin: cli
+                .value_of("margin")
+                .and_then(|n| n.parse::<usize>().ok())
+                .unwrap_or(0),
+            extrude: cli
+                .value_of("extrude")
+                .and_then(|n| n.parse::<usize>().ok())
+                .unwrap_or(0),
+            align: cli
+                .value_of("align")
+                .and_then(|n| n.parse::<usize>().ok())
+                .unwrap_or(0),
+            channels: match cli.value_of("channels") {
+                Some(suffixes) => ChannelSettings {
+                    enabled: true,
+                    suffixes: suffixes.to_string(),
+                },
+                None => ChannelSettings::default(),
+            },
+            rotate: cli.is_present("rotate"),
             scale: match cli.value_of("size") {
                 Some(size) => {
                     ImageScaling::Preview(size.parse::<f32>().unwrap_or(1024.0).max(32.0))
@@ -135,6 +690,81 @@ fn main() {
                 None => ImageScaling::Actual,
             },
             zip: cli.is_present("zip"),
+            json: cli.is_present("json"),
+            gif: cli.is_present("gif"),
+            sheet: cli.value_of("sheet").and_then(SheetFormat::from_name),
+            style: cli.value_of("style").and_then(StyleSheet::from_name),
+            format: {
+                let default = OutputSettings::default();
+                OutputSettings {
+                    format: cli
+                        .value_of("format")
+                        .and_then(OutputFormat::from_name)
+                        .unwrap_or_default(),
+                    compression: cli
+                        .value_of("compression")
+                        .and_then(PngCompression::from_name)
+                        .unwrap_or_default(),
+                    quality: cli
+                        .value_of("quality")
+                        .and_then(|n| n.parse::<u8>().ok())
+                        .map_or(default.quality, |q| q.clamp(1, 100)),
+                    flatten: !cli.is_present("no_flatten"),
+                    background: cli
+                        .value_of("background")
+                        .and_then(parse_color)
+                        .unwrap_or(default.background),
+                }
+            },
+            resampling: ResamplingSettings {
+                filter: cli
+                    .value_of("filter")
+                    .and_then(Resampling::from_name)
+                    .unwrap_or_default(),
+                linear: cli.is_present("linear"),
+            },
+            pixel_art: cli.is_present("pixel_art"),
+            blend: cli.is_present("blend"),
+            sixteen_bit: cli.is_present("sixteen_bit"),
+            tone_mapping: ToneMapSettings {
+                curve: cli
+                    .value_of("tone_map")
+                    .and_then(ToneMapping::from_name)
+                    .unwrap_or_default(),
+                exposure: cli
+                    .value_of("exposure")
+                    .and_then(|n| n.parse::<f32>().ok())
+                    .filter(|stops| stops.is_finite())
+                    .unwrap_or(0.0),
+            },
+            iterations: cli
+                .value_of("iterations")
+                .and_then(|n| n.parse::<usize>().ok()),
+            optimizer: {
+                let default = OptimizerSettings::default();
+                OptimizerSettings {
+                    enabled: cli.is_present("optimize"),
+                    attempts: cli
+                        .value_of("optimize")
+                        .and_then(|n| n.parse::<usize>().ok())
+                        .unwrap_or(default.attempts),
+                    seconds: cli
+                        .value_of("time")
+                        .and_then(|n| n.parse::<f32>().ok())
+                        .unwrap_or(default.seconds),
+                    seed: cli
+                        .value_of("seed")
+                        .and_then(|n| n.parse::<u64>().ok())
+                        .unwrap_or(default.seed),
+                }
+            },
+            paging: PageSettings {
+                max_size: cli
+                    .value_of("max_size")
+                    .and_then(|n| n.parse::<usize>().ok())
+                    .unwrap_or_default(),
+                power_of_two: cli.is_present("po2"),
+            },
         });
     } else {
         run_gui();
@@ -0,0 +1,169 @@
+use super::layout::PackingError;
+use super::loader::AnimationFrame;
+use super::modes::LayoutMode;
+use super::packer::PageSettings;
+use super::utils::{AspectRatio, RectSize};
+use crunch::Rect;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Rectangle in pixels.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Frame {
+    pub x: u32,
+    pub y: u32,
+    pub w: u32,
+    pub h: u32,
+}
+
+impl Frame {
+    pub fn new(x: u32, y: u32, w: u32, h: u32) -> Self {
+        Frame { x, y, w, h }
+    }
+
+    pub fn rect(&self) -> Rect {
+        Rect::new(
+            self.x as usize,
+            self.y as usize,
+            self.w as usize,
+            self.h as usize,
+        )
+    }
+}
+
+impl From<Rect> for Frame {
+    fn from(rect: Rect) -> Self {
+        Frame::new(rect.x as u32, rect.y as u32, rect.w as u32, rect.h as u32)
+    }
+}
+
+/// Where one source image ended up on exported canvas.
+/// `frame` is in output pixels and is already turned if image is `rotated`.
+/// `source` is the part of original image that is shown, in source pixels.
+/// `animation` tells which frame of animated source it is and how long it is shown.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Placement {
+    pub file: PathBuf,
+    pub original: RectSize,
+    pub source: Frame,
+    pub frame: Frame,
+    pub rotated: bool,
+    #[serde(default)]
+    pub animation: Option<AnimationFrame>,
+}
+
+impl Placement {
+    /// Size of image before it was turned, in output pixels.
+    pub fn unturned(&self) -> (u32, u32) {
+        match self.rotated {
+            true => (self.frame.h, self.frame.w),
+            false => (self.frame.w, self.frame.h),
+        }
+    }
+
+    /// Position of shown part inside whole source and size of the source, in output pixels.
+    /// `None` if the whole source is shown.
+    pub fn trim(&self) -> Option<(Frame, RectSize)> {
+        let source = self.source;
+        if source == Frame::new(0, 0, self.original.w as u32, self.original.h as u32) {
+            return None;
+        }
+        let (w, h) = self.unturned();
+        let (kx, ky) = (
+            w as f32 / source.w.max(1) as f32,
+            h as f32 / source.h.max(1) as f32,
+        );
+        Some((
+            Frame::new(
+                (source.x as f32 * kx).round() as u32,
+                (source.y as f32 * ky).round() as u32,
+                w,
+                h,
+            ),
+            RectSize::new(
+                ((self.original.w as f32 * kx).round() as usize).max(w as usize),
+                ((self.original.h as f32 * ky).round() as usize).max(h as usize),
+            ),
+        ))
+    }
+}
+
+/// Description of exported image, saved as JSON next to it.
+/// `scale` is the factor from packed layout to output pixels.
+/// `extrude` is how far edges of images are repeated around their frames.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LayoutMetadata {
+    pub image: String,
+    pub size: RectSize,
+    pub scale: f32,
+    #[serde(default)]
+    pub extrude: u32,
+    pub images: Vec<Placement>,
+}
+
+impl LayoutMetadata {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+}
+
+/// Packed image as it is stored in layout file, in units of packed layout.
+/// `rect` includes margin, `width` and `height` are the drawn size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPic {
+    pub file: PathBuf,
+    pub original: RectSize,
+    pub rect: Frame,
+    pub width: u32,
+    pub height: u32,
+    pub crop: Option<Frame>,
+    pub priority: f32,
+    pub rotated: bool,
+    #[serde(default)]
+    pub animation: Option<AnimationFrame>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedPage {
+    pub total_w: usize,
+    pub max_w: usize,
+    pub max_h: usize,
+    pub images: Vec<SavedPic>,
+}
+
+/// Everything needed to render packed images again without packing them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SavedLayout {
+    pub aspect: AspectRatio,
+    pub mode: LayoutMode,
+    pub margin: usize,
+    #[serde(default)]
+    pub extrude: usize,
+    #[serde(default)]
+    pub align: usize,
+    pub paging: PageSettings,
+    pub pages: Vec<SavedPage>,
+}
+
+impl SavedLayout {
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let layout: SavedLayout = serde_json::from_str(&fs::read_to_string(path)?)?;
+        match layout.pages.iter().all(|page| page.images.is_empty()) {
+            true => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                PackingError::NoImages.to_string(),
+            )),
+            false => Ok(layout),
+        }
+    }
+}
@@ -0,0 +1,701 @@
+use super::layout::{Packed, PackingError, Positions};
+use super::loader::Pic;
+use crunch::{Item, Rect};
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum LayoutMode {
+    #[default]
+    Packing,
+    Justified,
+    Grid,
+    Masonry,
+    Treemap,
+    Frames,
+}
+
+/// Grid dimensions, zero means it is computed automatically.
+/// With `fill` images are cropped to cells, otherwise they are letterboxed.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GridSettings {
+    pub columns: usize,
+    pub rows: usize,
+    pub fill: bool,
+}
+
+/// Number of columns, zero means it is computed to match aspect ratio.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct MasonrySettings {
+    pub columns: usize,
+}
+
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum TreemapWeight {
+    #[default]
+    FileSize,
+    PixelCount,
+    Priority,
+}
+
+impl TreemapWeight {
+    pub const ALL: [TreemapWeight; 3] = [
+        TreemapWeight::FileSize,
+        TreemapWeight::PixelCount,
+        TreemapWeight::Priority,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TreemapWeight::FileSize => "File Size",
+            TreemapWeight::PixelCount => "Pixels",
+            TreemapWeight::Priority => "Priority",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        TreemapWeight::ALL
+            .iter()
+            .find(|weight| {
+                weight
+                    .name()
+                    .replace(' ', "")
+                    .eq_ignore_ascii_case(name.trim())
+            })
+            .copied()
+    }
+
+    fn of(&self, pic: &Pic) -> f32 {
+        match self {
+            TreemapWeight::FileSize => pic.file_size.max(1) as f32,
+            TreemapWeight::PixelCount => (pic.original.w * pic.original.h) as f32,
+            TreemapWeight::Priority => pic.priority,
+        }
+    }
+}
+
+/// Area of each cell in treemap is proportional to `weight` of its image.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct TreemapSettings {
+    pub weight: TreemapWeight,
+}
+
+impl LayoutMode {
+    pub const ALL: [LayoutMode; 6] = [
+        LayoutMode::Packing,
+        LayoutMode::Justified,
+        LayoutMode::Grid,
+        LayoutMode::Masonry,
+        LayoutMode::Treemap,
+        LayoutMode::Frames,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LayoutMode::Packing => "Packing",
+            LayoutMode::Justified => "Justified",
+            LayoutMode::Grid => "Grid",
+            LayoutMode::Masonry => "Masonry",
+            LayoutMode::Treemap => "Treemap",
+            LayoutMode::Frames => "Frames",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        LayoutMode::ALL
+            .iter()
+            .find(|mode| mode.name().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+}
+
+/// Rows of images of equal height, each row stretched to fill canvas width.
+/// Keeps order of items. Row height is chosen to match the aspect ratio.
+pub fn justified(items: &[Item<Pic>], ratio: f32, margin: usize) -> Result<Packed, PackingError> {
+    if items.is_empty() {
+        return Err(PackingError::NoImages);
+    }
+    let aspects: Vec<f32> = items
+        .iter()
+        .map(|item| item.data.width as f32 / item.data.height as f32)
+        .collect();
+    let area = items
+        .iter()
+        .map(|item| (item.data.width as usize + margin) * (item.data.height as usize + margin))
+        .sum::<usize>() as f32;
+    let width = (area / ratio).sqrt().round().max(1.0);
+    let target = width * ratio;
+
+    //Taller rows hold less images, so total height grows with row height
+    let (mut low, mut high) = (1.0, target.max(2.0));
+    let mut rows = split_rows(&aspects, width, high, margin);
+    let mut rows_h = high;
+    let mut best = (rows_height(&aspects, &rows, width, high, margin) - target).abs();
+    for _ in 0..32 {
+        let row_h = (low + high) / 2.0;
+        let candidate = split_rows(&aspects, width, row_h, margin);
+        let height = rows_height(&aspects, &candidate, width, row_h, margin);
+        if (height - target).abs() < best {
+            best = (height - target).abs();
+            rows = candidate;
+            rows_h = row_h;
+        }
+        if height < target {
+            low = row_h;
+        } else {
+            high = row_h;
+        }
+    }
+
+    let mut positions = Positions::with_capacity(items.len());
+    let mut y = 0.0f32;
+    let heights = row_heights(&aspects, &rows, width, rows_h, margin);
+    for (row, row_h) in rows.into_iter().zip(heights) {
+        let top = y.round() as usize;
+        let pic_h = ((y + row_h).round() as usize).saturating_sub(top).max(1);
+        let mut x = 0.0f32;
+        for i in row {
+            let left = x.round() as usize;
+            x += aspects[i] * row_h;
+            let pic_w = (x.round() as usize).saturating_sub(left).max(1);
+            x += margin as f32;
+            positions.push((
+                Rect::new(left, top, pic_w + margin, pic_h + margin),
+                Pic {
+                    width: pic_w as u32,
+                    height: pic_h as u32,
+                    ..items[i].data.clone()
+                },
+            ));
+        }
+        y += row_h + margin as f32;
+    }
+    let height = y.round() as usize;
+    let total_w = (height as f32 / ratio).max(width) as usize;
+    Ok((total_w, height, positions))
+}
+
+fn split_rows(aspects: &[f32], width: f32, row_h: f32, margin: usize) -> Vec<Range<usize>> {
+    let mut rows = Vec::<Range<usize>>::new();
+    let mut start = 0;
+    let mut row_aspect = 0.0;
+    for (i, aspect) in aspects.iter().enumerate() {
+        row_aspect += aspect;
+        if row_aspect * row_h + ((i + 1 - start) * margin) as f32 >= width {
+            rows.push(start..i + 1);
+            start = i + 1;
+            row_aspect = 0.0;
+        }
+    }
+    if start < aspects.len() {
+        rows.push(start..aspects.len());
+    }
+    rows
+}
+
+fn row_height(aspects: &[f32], row: &Range<usize>, width: f32, margin: usize) -> f32 {
+    let free_w = (width - (row.len() * margin) as f32).max(row.len() as f32);
+    free_w / aspects[row.clone()].iter().sum::<f32>()
+}
+
+//Last row is not stretched above mean height of full rows, or `row_h` if it is the only one,
+//so a few narrow images at the end don't grow huge
+fn row_heights(
+    aspects: &[f32],
+    rows: &[Range<usize>],
+    width: f32,
+    row_h: f32,
+    margin: usize,
+) -> Vec<f32> {
+    let mut heights: Vec<f32> = rows
+        .iter()
+        .map(|row| row_height(aspects, row, width, margin))
+        .collect();
+    if let Some((last, full)) = heights.split_last_mut() {
+        let limit = match full.is_empty() {
+            true => row_h,
+            false => full.iter().sum::<f32>() / full.len() as f32,
+        };
+        *last = last.min(limit);
+    }
+    heights
+}
+
+fn rows_height(
+    aspects: &[f32],
+    rows: &[Range<usize>],
+    width: f32,
+    row_h: f32,
+    margin: usize,
+) -> f32 {
+    row_heights(aspects, rows, width, row_h, margin)
+        .iter()
+        .map(|height| height + margin as f32)
+        .sum()
+}
+
+/// Uniform cells in rows and columns, filled in order of items.
+pub fn grid(
+    items: &[Item<Pic>],
+    ratio: f32,
+    margin: usize,
+    settings: &GridSettings,
+) -> Result<Packed, PackingError> {
+    if items.is_empty() {
+        return Err(PackingError::NoImages);
+    }
+    let num = items.len();
+    let mean_aspect = items
+        .iter()
+        .map(|item| item.data.height as f32 / item.data.width as f32)
+        .sum::<f32>()
+        / num as f32;
+    let (columns, rows) = grid_size(num, ratio, mean_aspect, settings);
+
+    //Cell of mean area, shaped so that the whole grid matches aspect ratio
+    let mean_area = items
+        .iter()
+        .map(|item| item.data.width as f32 * item.data.height as f32)
+        .sum::<f32>()
+        / num as f32;
+    let cell_ratio = ratio * columns as f32 / rows as f32;
+    let cell_w = (mean_area / cell_ratio).sqrt().round().max(1.0) as usize;
+    let cell_h = (cell_ratio * (cell_w + margin) as f32 - margin as f32)
+        .round()
+        .max(1.0) as usize;
+
+    let positions = items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| {
+            let (x, y) = (
+                (i % columns) * (cell_w + margin),
+                (i / columns) * (cell_h + margin),
+            );
+            if settings.fill {
+                let pic = Pic {
+                    width: cell_w as u32,
+                    height: cell_h as u32,
+                    crop: Some(center_crop(item.data.region(), cell_w, cell_h)),
+                    ..item.data.clone()
+                };
+                (Rect::new(x, y, cell_w + margin, cell_h + margin), pic)
+            } else {
+                let (w, h) = fit_to_cell(item.data.width, item.data.height, cell_w, cell_h);
+                let (dx, dy) = ((cell_w - w) / 2, (cell_h - h) / 2);
+                let pic = Pic {
+                    width: w as u32,
+                    height: h as u32,
+                    ..item.data.clone()
+                };
+                (Rect::new(x + dx, y + dy, w + margin, h + margin), pic)
+            }
+        })
+        .collect();
+    let width = columns * (cell_w + margin);
+    let height = rows * (cell_h + margin);
+    let total_w = (height as f32 / ratio).max(width as f32) as usize;
+    Ok((total_w, height, positions))
+}
+
+/// Cells of the largest frame size, filled in playback order without scaling.
+/// Frames are ordered by file and then by their index in animation.
+pub fn frames(
+    items: &[Item<Pic>],
+    ratio: f32,
+    margin: usize,
+    settings: &GridSettings,
+) -> Result<Packed, PackingError> {
+    if items.is_empty() {
+        return Err(PackingError::NoImages);
+    }
+    let mut ordered: Vec<&Pic> = items.iter().map(|item| &item.data).collect();
+    ordered.sort_by_key(|pic| (&pic.file, pic.animation.map(|frame| frame.index)));
+    let cell_w = ordered.iter().map(|pic| pic.width).max().unwrap_or(1) as usize;
+    let cell_h = ordered.iter().map(|pic| pic.height).max().unwrap_or(1) as usize;
+    let aspect = (cell_h + margin) as f32 / (cell_w + margin) as f32;
+    let (columns, rows) = grid_size(ordered.len(), ratio, aspect, settings);
+
+    let positions = ordered
+        .into_iter()
+        .enumerate()
+        .map(|(i, pic)| {
+            let (w, h) = (pic.width as usize, pic.height as usize);
+            let (x, y) = (
+                (i % columns) * (cell_w + margin) + (cell_w - w) / 2,
+                (i / columns) * (cell_h + margin) + (cell_h - h) / 2,
+            );
+            (Rect::new(x, y, w + margin, h + margin), pic.clone())
+        })
+        .collect();
+    let width = columns * (cell_w + margin);
+    let height = rows * (cell_h + margin);
+    let total_w = (height as f32 / ratio).max(width as f32) as usize;
+    Ok((total_w, height, positions))
+}
+
+//Columns and rows for `num` cells of `aspect`, automatic ones match the ratio
+fn grid_size(num: usize, ratio: f32, aspect: f32, settings: &GridSettings) -> (usize, usize) {
+    match (settings.columns, settings.rows) {
+        (0, 0) => (1..=num)
+            .map(|c| (c, num.div_ceil(c)))
+            .min_by(|a, b| {
+                let cost = |(c, r): (usize, usize)| {
+                    (ratio * c as f32 / r as f32 / aspect).ln().abs()
+                        + (c * r - num) as f32 / num as f32
+                };
+                cost(*a).total_cmp(&cost(*b))
+            })
+            .unwrap_or((1, num)),
+        (0, r) => (num.div_ceil(r), r),
+        (c, r) => (c, r.max(num.div_ceil(c))),
+    }
+}
+
+/// Columns of equal width, each image is dropped into the shortest one.
+pub fn masonry(
+    items: &[Item<Pic>],
+    ratio: f32,
+    margin: usize,
+    settings: &MasonrySettings,
+) -> Result<Packed, PackingError> {
+    if items.is_empty() {
+        return Err(PackingError::NoImages);
+    }
+    let aspects: Vec<f32> = items
+        .iter()
+        .map(|item| item.data.height as f32 / item.data.width as f32)
+        .collect();
+    let area = items
+        .iter()
+        .map(|item| item.data.width as f32 * item.data.height as f32)
+        .sum::<f32>();
+    let column_w = (area / aspects.iter().sum::<f32>()).sqrt().round().max(1.0) as usize;
+
+    let columns = match settings.columns {
+        0 => {
+            //Stacked height is shared by columns, so its root gives a close guess
+            let stacked = aspects
+                .iter()
+                .map(|a| a * column_w as f32 + margin as f32)
+                .sum::<f32>();
+            let guess = (stacked / ((column_w + margin) as f32 * ratio))
+                .sqrt()
+                .round()
+                .clamp(1.0, items.len() as f32) as usize;
+            (guess.saturating_sub(2).max(1)..=(guess + 2).min(items.len()))
+                .min_by(|a, b| {
+                    let cost = |columns: usize| {
+                        let (_, height) =
+                            drop_to_columns(items, &aspects, column_w, margin, columns);
+                        let width = columns * (column_w + margin);
+                        (height as f32 / width as f32 / ratio).ln().abs()
+                    };
+                    cost(*a).total_cmp(&cost(*b))
+                })
+                .unwrap_or(guess)
+        }
+        c => c,
+    };
+
+    let (positions, height) = drop_to_columns(items, &aspects, column_w, margin, columns);
+    let width = columns * (column_w + margin);
+    let total_w = (height as f32 / ratio).max(width as f32) as usize;
+    Ok((total_w, height, positions))
+}
+
+fn drop_to_columns(
+    items: &[Item<Pic>],
+    aspects: &[f32],
+    column_w: usize,
+    margin: usize,
+    columns: usize,
+) -> (Positions, usize) {
+    let mut bottoms = vec![0; columns];
+    let mut positions = Positions::with_capacity(items.len());
+    for (item, aspect) in items.iter().zip(aspects) {
+        let (column, y) = bottoms
+            .iter()
+            .copied()
+            .enumerate()
+            .min_by_key(|(_, y)| *y)
+            .unwrap_or_default();
+        let h = ((aspect * column_w as f32).round() as usize).max(1);
+        positions.push((
+            Rect::new(
+                column * (column_w + margin),
+                y,
+                column_w + margin,
+                h + margin,
+            ),
+            Pic {
+                width: column_w as u32,
+                height: h as u32,
+                ..item.data.clone()
+            },
+        ));
+        bottoms[column] += h + margin;
+    }
+    (positions, bottoms.into_iter().max().unwrap_or_default())
+}
+
+/// Squarified treemap, fills whole canvas. Images are cropped to their cells.
+pub fn treemap(
+    items: &[Item<Pic>],
+    ratio: f32,
+    margin: usize,
+    settings: &TreemapSettings,
+) -> Result<Packed, PackingError> {
+    if items.is_empty() {
+        return Err(PackingError::NoImages);
+    }
+    let area = items
+        .iter()
+        .map(|item| (item.data.width as usize + margin) * (item.data.height as usize + margin))
+        .sum::<usize>() as f32;
+
+    let weights: Vec<f32> = items
+        .iter()
+        .map(|item| settings.weight.of(&item.data).max(f32::EPSILON))
+        .collect();
+    let total = weights.iter().sum::<f32>();
+    let mut order: Vec<usize> = (0..items.len()).collect();
+    order.sort_by(|a, b| weights[*b].total_cmp(&weights[*a]));
+    //Every cell keeps room for margin and a few pixels, canvas grows to hold them
+    let least = ((margin + 2) * 2).pow(2) as f32;
+    let areas: Vec<f32> = order
+        .iter()
+        .map(|i| (weights[*i] / total * area).max(least))
+        .collect();
+    let area = areas.iter().sum::<f32>();
+    let width = (area / ratio).sqrt().max(1.0);
+    let height = area / width;
+
+    let cells = squarify(&areas, [0.0, 0.0, width, height]);
+    let positions = order
+        .iter()
+        .zip(cells)
+        .map(|(i, [x, y, w, h])| {
+            let (left, top) = (x.round() as usize, y.round() as usize);
+            let cell_w = ((x + w).round() as usize).saturating_sub(left).max(1);
+            let cell_h = ((y + h).round() as usize).saturating_sub(top).max(1);
+            let (pic_w, pic_h) = (
+                cell_w.saturating_sub(margin).max(1),
+                cell_h.saturating_sub(margin).max(1),
+            );
+            let pic = Pic {
+                width: pic_w as u32,
+                height: pic_h as u32,
+                crop: Some(center_crop(items[*i].data.region(), pic_w, pic_h)),
+                ..items[*i].data.clone()
+            };
+            (Rect::new(left, top, cell_w, cell_h), pic)
+        })
+        .collect();
+    Ok((width.round() as usize, height.round() as usize, positions))
+}
+
+//Lays out descending `areas` in strips along shorter side of `rect`
+fn squarify(areas: &[f32], rect: [f32; 4]) -> Vec<[f32; 4]> {
+    let [mut x, mut y, mut w, mut h] = rect;
+    let mut cells = Vec::<[f32; 4]>::with_capacity(areas.len());
+    let mut start = 0;
+    while start < areas.len() {
+        let side = w.min(h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst_aspect(&areas[start..=end], side) <= worst_aspect(&areas[start..end], side)
+        {
+            end += 1;
+        }
+        let strip_area = areas[start..end].iter().sum::<f32>();
+        if w >= h {
+            let strip_w = (strip_area / h).min(w);
+            let mut cell_y = y;
+            for a in &areas[start..end] {
+                let cell_h = a / strip_w;
+                cells.push([x, cell_y, strip_w, cell_h]);
+                cell_y += cell_h;
+            }
+            x += strip_w;
+            w -= strip_w;
+        } else {
+            let strip_h = (strip_area / w).min(h);
+            let mut cell_x = x;
+            for a in &areas[start..end] {
+                let cell_w = a / strip_h;
+                cells.push([cell_x, y, cell_w, strip_h]);
+                cell_x += cell_w;
+            }
+            y += strip_h;
+            h -= strip_h;
+        }
+        start = end;
+    }
+    cells
+}
+
+fn worst_aspect(row: &[f32], side: f32) -> f32 {
+    let sum = row.iter().sum::<f32>();
+    let (min, max) = row.iter().fold((f32::MAX, 0.0f32), |(min, max), a| {
+        (min.min(*a), max.max(*a))
+    });
+    let side = side * side;
+    (side * max / (sum * sum)).max(sum * sum / (side * min))
+}
+
+fn fit_to_cell(width: u32, height: u32, cell_w: usize, cell_h: usize) -> (usize, usize) {
+    let k = (cell_w as f32 / width as f32).min(cell_h as f32 / height as f32);
+    (
+        ((width as f32 * k) as usize).clamp(1, cell_w),
+        ((height as f32 * k) as usize).clamp(1, cell_h),
+    )
+}
+
+//Largest part of `region` of the same aspect as `w` x `h`, centered
+fn center_crop(region: Rect, w: usize, h: usize) -> Rect {
+    let target = h as f32 / w as f32;
+    if (region.h as f32 / region.w as f32) < target {
+        let crop_w = ((region.h as f32 / target).round() as usize).clamp(1, region.w);
+        Rect::new(
+            region.x + (region.w - crop_w) / 2,
+            region.y,
+            crop_w,
+            region.h,
+        )
+    } else {
+        let crop_h = ((region.w as f32 * target).round() as usize).clamp(1, region.h);
+        Rect::new(
+            region.x,
+            region.y + (region.h - crop_h) / 2,
+            region.w,
+            crop_h,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::loader::{assert_apart, test_items};
+    use crunch::Rotation;
+
+    fn assert_packed_apart((total_w, height, positions): &Packed, num: usize) {
+        assert_eq!(positions.len(), num);
+        assert_apart(Rect::of_size(*total_w, *height), positions);
+    }
+
+    #[test]
+    fn justified_rows_stay_apart() {
+        let items = test_items(23, Rotation::None);
+        for margin in [0, 3] {
+            for ratio in [0.5, 1.0, 1.7] {
+                assert_packed_apart(&justified(&items, ratio, margin).unwrap(), items.len());
+            }
+        }
+        assert!(matches!(
+            justified(&[], 1.0, 0),
+            Err(PackingError::NoImages)
+        ));
+    }
+
+    #[test]
+    fn justified_last_row_is_not_stretched() {
+        let mut items: Vec<Item<Pic>> = (0..12)
+            .map(|_| Item::new(Pic::blank(200, 100), 200, 100, Rotation::None))
+            .collect();
+        items.push(Item::new(Pic::blank(20, 100), 20, 100, Rotation::None));
+        let (_, _, positions) = justified(&items, 1.0, 0).unwrap();
+        let first_h = positions[0].1.height;
+        let last = &positions.last().unwrap().1;
+        assert!(last.height <= first_h + 1, "{} > {}", last.height, first_h);
+        assert!(last.width < last.height);
+    }
+
+    #[test]
+    fn grid_cells_stay_apart() {
+        let items = test_items(23, Rotation::None);
+        for margin in [0, 3] {
+            for ratio in [0.5, 1.0, 1.7] {
+                for fill in [false, true] {
+                    let settings = GridSettings {
+                        fill,
+                        ..GridSettings::default()
+                    };
+                    let packed = grid(&items, ratio, margin, &settings).unwrap();
+                    assert_packed_apart(&packed, items.len());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn grid_holds_every_cell() {
+        let auto = GridSettings::default();
+        for num in 1..40 {
+            let (columns, rows) = grid_size(num, 1.0, 1.0, &auto);
+            assert!(columns * rows >= num);
+            assert!(columns * (rows - 1) < num, "{} has empty row", num);
+            let fixed = GridSettings {
+                columns: 3,
+                rows: 1,
+                ..auto
+            };
+            let (columns, rows) = grid_size(num, 1.0, 1.0, &fixed);
+            assert_eq!(columns, 3);
+            assert!(columns * rows >= num);
+            let (columns, rows) = grid_size(num, 1.0, 1.0, &GridSettings { rows: 4, ..auto });
+            assert_eq!(rows, 4);
+            assert!(columns * rows >= num);
+        }
+    }
+
+    #[test]
+    fn masonry_columns_stay_apart() {
+        let items = test_items(23, Rotation::None);
+        for margin in [0, 3] {
+            for ratio in [0.5, 1.0, 1.7] {
+                for columns in [0, 1, 4] {
+                    let settings = MasonrySettings { columns };
+                    let packed = masonry(&items, ratio, margin, &settings).unwrap();
+                    assert_packed_apart(&packed, items.len());
+                    let column_w = packed.2[0].0.w;
+                    assert!(packed.2.iter().all(|(rect, _)| rect.w == column_w));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn treemap_cells_stay_apart() {
+        let items = test_items(23, Rotation::None);
+        for margin in [0, 3] {
+            for ratio in [0.5, 1.0, 1.7] {
+                let packed = treemap(&items, ratio, margin, &TreemapSettings::default()).unwrap();
+                assert_packed_apart(&packed, items.len());
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_treemap_weights_stay_apart() {
+        let mut items = test_items(150, Rotation::None);
+        for (i, item) in items.iter_mut().enumerate() {
+            item.data.priority = match i {
+                0 => 1000.0,
+                _ => 0.001,
+            };
+        }
+        let settings = TreemapSettings {
+            weight: TreemapWeight::Priority,
+        };
+        for margin in [0, 2] {
+            let packed = treemap(&items, 1.0, margin, &settings).unwrap();
+            assert_packed_apart(&packed, items.len());
+            assert!(packed
+                .2
+                .iter()
+                .all(|(rect, _)| rect.w > margin && rect.h > margin));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+use super::layout::{Packed, PackingError};
+use super::loader::Pic;
+use crunch::Item;
+use rand::{rngs::StdRng, seq::SliceRandom, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::time::Instant;
+
+/// Budget of packing optimization. Zero `seconds` means no time limit.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OptimizerSettings {
+    pub enabled: bool,
+    pub attempts: usize,
+    pub seconds: f32,
+    pub seed: u64,
+}
+impl Default for OptimizerSettings {
+    fn default() -> Self {
+        OptimizerSettings {
+            enabled: false,
+            attempts: 16,
+            seconds: 2.0,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum ItemOrder {
+    Priority,
+    Area,
+    Height,
+    Width,
+    Perimeter,
+    Shuffle(u64),
+}
+
+impl ItemOrder {
+    const SORTED: [ItemOrder; 5] = [
+        ItemOrder::Priority,
+        ItemOrder::Area,
+        ItemOrder::Height,
+        ItemOrder::Width,
+        ItemOrder::Perimeter,
+    ];
+
+    /// Sorts items descending, `Priority` is the one crunch uses.
+    /// `Shuffle` is random, but repeats for the same seed.
+    pub fn apply(&self, items: &mut [Item<Pic>]) {
+        match self {
+            ItemOrder::Priority => {
+                items.sort_by_key(|item| Reverse(item.w * item.h + item.w.max(item.h)))
+            }
+            ItemOrder::Area => items.sort_by_key(|item| Reverse(item.w * item.h)),
+            ItemOrder::Height => items.sort_by_key(|item| Reverse((item.h, item.w))),
+            ItemOrder::Width => items.sort_by_key(|item| Reverse((item.w, item.h))),
+            ItemOrder::Perimeter => items.sort_by_key(|item| Reverse(item.w + item.h)),
+            ItemOrder::Shuffle(seed) => items.shuffle(&mut StdRng::seed_from_u64(*seed)),
+        }
+    }
+}
+
+/// Packs items in several orders and keeps the layout that fills target ratio best.
+pub fn optimize<F>(
+    items: &[Item<Pic>],
+    ratio: f32,
+    settings: &OptimizerSettings,
+    mut pack: F,
+) -> Result<Packed, PackingError>
+where
+    F: FnMut(&[Item<Pic>]) -> Result<Packed, PackingError>,
+{
+    let start = Instant::now();
+    let mut ordered = items.to_vec();
+    let mut best: Option<(f32, Packed)> = None;
+    let mut error = PackingError::NoImages;
+    for attempt in 0..settings.attempts.max(1) {
+        if attempt > 0 && settings.seconds > 0.0 && start.elapsed().as_secs_f32() > settings.seconds
+        {
+            break;
+        }
+        let order = ItemOrder::SORTED
+            .get(attempt)
+            .copied()
+            .unwrap_or(ItemOrder::Shuffle(
+                settings.seed.wrapping_add(attempt as u64),
+            ));
+        order.apply(&mut ordered);
+        match pack(&ordered) {
+            Ok(packed) => {
+                let score = fill_ratio(&packed, ratio);
+                if best
+                    .as_ref()
+                    .is_none_or(|(best_score, _)| score > *best_score)
+                {
+                    best = Some((score, packed));
+                }
+            }
+            Err(err) => error = err,
+        }
+    }
+    best.map(|(_, packed)| packed).ok_or(error)
+}
+
+/// Share of canvas with target `ratio` covered by items.
+pub fn fill_ratio(packed: &Packed, ratio: f32) -> f32 {
+    let (max_w, max_h, used) =
+        packed
+            .2
+            .iter()
+            .fold((0, 0, 0), |(max_w, max_h, used), (rect, _)| {
+                (
+                    max_w.max(rect.right()),
+                    max_h.max(rect.bottom()),
+                    used + rect.area(),
+                )
+            });
+    let side = (max_w as f32).max(max_h as f32 / ratio);
+    used as f32 / (side * side * ratio).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::PackingAlgorithm;
+    use crate::loader::test_items;
+    use crunch::{Rect, Rotation};
+
+    fn skyline(items: &[Item<Pic>]) -> Result<Packed, PackingError> {
+        PackingAlgorithm::Skyline
+            .engine()
+            .pack(Rect::of_size(120, 2000), items)
+            .map(|positions| (120, 2000, positions))
+            .map_err(|_| PackingError::DoesNotFit(1))
+    }
+
+    fn settings(attempts: usize, seconds: f32) -> OptimizerSettings {
+        OptimizerSettings {
+            enabled: true,
+            attempts,
+            seconds,
+            seed: 7,
+        }
+    }
+
+    #[test]
+    fn chosen_order_fills_at_least_as_priority_order() {
+        let mut items = test_items(40, Rotation::None);
+        let best = optimize(&items, 1.0, &settings(24, 0.0), skyline).unwrap();
+        ItemOrder::Priority.apply(&mut items);
+        let baseline = skyline(&items).unwrap();
+        assert!(fill_ratio(&best, 1.0) >= fill_ratio(&baseline, 1.0));
+    }
+
+    #[test]
+    fn same_seed_gives_same_layout() {
+        let items = test_items(40, Rotation::None);
+        let rects = |packed: Packed| -> Vec<(usize, usize, usize, usize)> {
+            packed
+                .2
+                .iter()
+                .map(|(rect, _)| (rect.x, rect.y, rect.w, rect.h))
+                .collect()
+        };
+        let first = optimize(&items, 1.0, &settings(12, 0.0), skyline).unwrap();
+        let second = optimize(&items, 1.0, &settings(12, 0.0), skyline).unwrap();
+        assert_eq!(rects(first), rects(second));
+    }
+
+    #[test]
+    fn attempts_and_time_limit_bound_packing() {
+        let items = test_items(10, Rotation::None);
+        let count = |settings: OptimizerSettings, pause: u64| {
+            let mut calls = 0;
+            let _ = optimize(&items, 1.0, &settings, |items| {
+                calls += 1;
+                std::thread::sleep(std::time::Duration::from_millis(pause));
+                skyline(items)
+            });
+            calls
+        };
+        assert_eq!(count(settings(0, 0.0), 0), 1);
+        //Zero seconds means no time limit
+        assert_eq!(count(settings(30, 0.0), 1), 30);
+        //First attempt always runs, later ones stop after time limit
+        assert_eq!(count(settings(30, 0.001), 5), 1);
+    }
+
+    #[test]
+    fn shuffle_seed_wraps_around() {
+        let items = test_items(10, Rotation::None);
+        let settings = OptimizerSettings {
+            seed: u64::MAX,
+            ..settings(8, 0.0)
+        };
+        assert!(optimize(&items, 1.0, &settings, skyline).is_ok());
+    }
+}
@@ -0,0 +1,304 @@
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::error::{EncodingError, ImageFormatHint};
+use image::{
+    ColorType, DynamicImage, GenericImageView, ImageEncoder, ImageError, ImageFormat, ImageResult,
+    Rgb, RgbImage, RgbaImage,
+};
+use image_webp::{ColorType as WebPColor, WebPEncoder};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+/// File formats of exported image.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    WebP,
+    Tiff,
+    Bmp,
+    Tga,
+}
+
+impl OutputFormat {
+    pub const ALL: [OutputFormat; 6] = [
+        OutputFormat::Png,
+        OutputFormat::Jpeg,
+        OutputFormat::WebP,
+        OutputFormat::Tiff,
+        OutputFormat::Bmp,
+        OutputFormat::Tga,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "PNG",
+            OutputFormat::Jpeg => "JPEG",
+            OutputFormat::WebP => "WebP",
+            OutputFormat::Tiff => "TIFF",
+            OutputFormat::Bmp => "BMP",
+            OutputFormat::Tga => "TGA",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = match name.trim().to_lowercase().as_str() {
+            "jpg" => "jpeg".to_string(),
+            "tif" => "tiff".to_string(),
+            name => name.to_string(),
+        };
+        OutputFormat::ALL
+            .iter()
+            .find(|format| format.name().eq_ignore_ascii_case(&name))
+            .copied()
+    }
+
+    pub fn supports_sixteen_bit(&self) -> bool {
+        matches!(self, OutputFormat::Png | OutputFormat::Tiff)
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg => "jpg",
+            OutputFormat::WebP => "webp",
+            OutputFormat::Tiff => "tif",
+            OutputFormat::Bmp => "bmp",
+            OutputFormat::Tga => "tga",
+        }
+    }
+}
+
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum PngCompression {
+    Fast,
+    #[default]
+    Default,
+    Best,
+}
+
+impl PngCompression {
+    pub const ALL: [PngCompression; 3] = [
+        PngCompression::Fast,
+        PngCompression::Default,
+        PngCompression::Best,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PngCompression::Fast => "Fast",
+            PngCompression::Default => "Default",
+            PngCompression::Best => "Best",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        PngCompression::ALL
+            .iter()
+            .find(|compression| compression.name().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+
+    fn compression_type(&self) -> CompressionType {
+        match self {
+            PngCompression::Fast => CompressionType::Fast,
+            PngCompression::Default => CompressionType::Default,
+            PngCompression::Best => CompressionType::Best,
+        }
+    }
+}
+
+/// Format of exported image and options of its encoder.
+/// JPEG has no alpha, with `flatten` images are laid over `background`,
+/// otherwise transparent pixels keep their color.
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OutputSettings {
+    pub format: OutputFormat,
+    pub compression: PngCompression,
+    pub quality: u8,
+    pub flatten: bool,
+    pub background: [u8; 3],
+}
+
+impl Default for OutputSettings {
+    fn default() -> Self {
+        OutputSettings {
+            format: OutputFormat::default(),
+            compression: PngCompression::default(),
+            quality: 90,
+            flatten: true,
+            background: [255, 255, 255],
+        }
+    }
+}
+
+impl OutputSettings {
+    pub fn extension(&self) -> &'static str {
+        self.format.extension()
+    }
+
+    /// Writes `image` with its bit depth if format can hold it, otherwise with 8 bits.
+    pub fn save(&self, image: &DynamicImage, path: &Path) -> ImageResult<()> {
+        let (w, h) = image.dimensions();
+        match self.format {
+            OutputFormat::Png => {
+                let file = BufWriter::new(File::create(path)?);
+                PngEncoder::new_with_quality(
+                    file,
+                    self.compression.compression_type(),
+                    FilterType::Sub,
+                )
+                .write_image(image.as_bytes(), w, h, image.color())
+            }
+            OutputFormat::Jpeg => {
+                let mut file = BufWriter::new(File::create(path)?);
+                JpegEncoder::new_with_quality(&mut file, self.quality.clamp(1, 100)).encode(
+                    &self.opaque(&image.to_rgba8()),
+                    w,
+                    h,
+                    ColorType::Rgb8,
+                )
+            }
+            //WebP encoder is always lossless
+            OutputFormat::WebP => {
+                let file = BufWriter::new(File::create(path)?);
+                WebPEncoder::new(file)
+                    .encode(&image.to_rgba8(), w, h, WebPColor::Rgba8)
+                    .map_err(|err| {
+                        ImageError::Encoding(EncodingError::new(
+                            ImageFormatHint::Exact(ImageFormat::WebP),
+                            err,
+                        ))
+                    })
+            }
+            OutputFormat::Tiff => image.save_with_format(path, ImageFormat::Tiff),
+            OutputFormat::Bmp => image.to_rgba8().save_with_format(path, ImageFormat::Bmp),
+            OutputFormat::Tga => image.to_rgba8().save_with_format(path, ImageFormat::Tga),
+        }
+    }
+
+    fn opaque(&self, image: &RgbaImage) -> RgbImage {
+        RgbImage::from_fn(image.width(), image.height(), |x, y| {
+            let pixel = image.get_pixel(x, y);
+            match self.flatten {
+                true => {
+                    let alpha = pixel[3] as u32;
+                    Rgb([0, 1, 2].map(|i| {
+                        ((pixel[i] as u32 * alpha + self.background[i] as u32 * (255 - alpha))
+                            / 255) as u8
+                    }))
+                }
+                false => Rgb([pixel[0], pixel[1], pixel[2]]),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use image_webp::WebPDecoder;
+    use std::io::BufReader;
+
+    #[test]
+    fn flatten_blends_over_background() {
+        let image = RgbaImage::from_fn(3, 1, |x, _| Rgba([255, 0, 0, [255, 128, 0][x as usize]]));
+        let settings = OutputSettings {
+            format: OutputFormat::Jpeg,
+            background: [255, 255, 255],
+            ..Default::default()
+        };
+        let flat = settings.opaque(&image);
+        assert_eq!(flat.get_pixel(0, 0), &Rgb([255, 0, 0]));
+        assert_eq!(flat.get_pixel(1, 0), &Rgb([255, 127, 127]));
+        assert_eq!(flat.get_pixel(2, 0), &Rgb([255, 255, 255]));
+        let kept = OutputSettings {
+            flatten: false,
+            ..settings
+        }
+        .opaque(&image);
+        assert_eq!(kept.get_pixel(2, 0), &Rgb([255, 0, 0]));
+    }
+
+    #[test]
+    fn every_format_is_read_back() {
+        let folder = std::env::temp_dir().join("ppp_output");
+        std::fs::create_dir_all(&folder).unwrap();
+        let image = RgbaImage::from_fn(16, 8, |x, y| Rgba([x as u8 * 16, y as u8 * 32, 90, 255]));
+        for format in OutputFormat::ALL {
+            let settings = OutputSettings {
+                format,
+                quality: 100,
+                ..Default::default()
+            };
+            let path = folder.join(format!("round_trip.{}", format.extension()));
+            settings
+                .save(&DynamicImage::ImageRgba8(image.clone()), &path)
+                .unwrap();
+            let decoded = match format {
+                OutputFormat::WebP => {
+                    let mut decoder =
+                        WebPDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+                    let mut buffer = vec![0; decoder.output_buffer_size().unwrap()];
+                    decoder.read_image(&mut buffer).unwrap();
+                    RgbaImage::from_raw(16, 8, buffer).unwrap()
+                }
+                _ => image::open(&path).unwrap().to_rgba8(),
+            };
+            assert_eq!(decoded.dimensions(), (16, 8), "{}", format.name());
+            //JPEG is lossy even at best quality
+            let tolerance = match format {
+                OutputFormat::Jpeg => 8,
+                _ => 0,
+            };
+            for (a, b) in decoded.pixels().zip(image.pixels()) {
+                for i in 0..4 {
+                    assert!(
+                        (a[i] as i32 - b[i] as i32).abs() <= tolerance,
+                        "{} changed {:?} to {:?}",
+                        format.name(),
+                        b,
+                        a
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn sixteen_bits_are_kept_only_by_png_and_tiff() {
+        let folder = std::env::temp_dir().join("ppp_output_deep");
+        std::fs::create_dir_all(&folder).unwrap();
+        let image = image::ImageBuffer::from_fn(4, 2, |x, y| {
+            Rgba([x as u16 * 0x1234 + 1, y as u16 * 0x4321 + 3, 0x0101, 0xffff])
+        });
+        for format in OutputFormat::ALL {
+            let path = folder.join(format!("deep.{}", format.extension()));
+            OutputSettings {
+                format,
+                ..Default::default()
+            }
+            .save(&DynamicImage::ImageRgba16(image.clone()), &path)
+            .unwrap();
+            if format == OutputFormat::WebP {
+                let decoder = WebPDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+                assert_eq!(decoder.output_buffer_size(), Some(4 * 2 * 4));
+                continue;
+            }
+            let decoded = image::open(&path).unwrap();
+            match format.supports_sixteen_bit() {
+                true => assert_eq!(decoded.to_rgba16(), image, "{}", format.name()),
+                false => assert!(
+                    matches!(decoded.color(), ColorType::Rgb8 | ColorType::Rgba8),
+                    "{} is {:?}",
+                    format.name(),
+                    decoded.color()
+                ),
+            }
+        }
+    }
+}
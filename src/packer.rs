@@ -1,10 +1,31 @@
-use super::loader::{load_new_items, Pic};
+use super::channels::{ChannelSettings, Stems};
+use super::layout::{LayoutEngine, Packed, PackingAlgorithm, PackingError};
+use super::loader::{
+    is_high_dynamic_range, load_new_items, opaque_bounds, open_animation, open_image, Pic,
+};
+use super::metadata::{Frame, LayoutMetadata, Placement, SavedLayout, SavedPage, SavedPic};
+use super::modes::{
+    frames, grid, justified, masonry, treemap, GridSettings, LayoutMode, MasonrySettings,
+    TreemapSettings,
+};
+use super::optimizer::{optimize, ItemOrder, OptimizerSettings};
+use super::output::OutputSettings;
+use super::resampling::{resize, Canvas, Resampling, ResamplingSettings};
+use super::sheets::{SheetFormat, StyleSheet};
+use super::tonemapping::ToneMapSettings;
 use super::utils::*;
-use crunch::{pack, Item, PackedItems, Rect, Rotation};
+use crunch::{Item, Rect, Rotation};
 use eframe::egui::DroppedFile;
-use image::imageops::{replace, resize, thumbnail, FilterType};
-use image::{DynamicImage, ImageResult, RgbaImage};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::{crop_imm, replace, rotate90};
+use image::{
+    Delay, DynamicImage, Frame as AnimationFrame, GenericImageView, ImageError, ImageResult,
+    Primitive, RgbaImage, SubImage,
+};
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io;
 use std::path::{Path, PathBuf};
 
 struct PackingResult {
@@ -14,19 +35,79 @@ struct PackingResult {
     positions: Vec<(Rect, Pic)>,
 }
 
+/// Limit of page side in pixels, zero means everything goes to a single page.
+/// Pages are used only by packing mode, images on them keep actual size.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct PageSettings {
+    pub max_size: usize,
+    pub power_of_two: bool,
+}
+
+impl PageSettings {
+    /// Usable side of page, with `power_of_two` it is rounded down to power of two.
+    pub fn side(&self) -> usize {
+        match self.power_of_two && self.max_size > 0 {
+            true => 1 << self.max_size.ilog2(),
+            false => self.max_size,
+        }
+    }
+}
+
+/// How images are drawn on exported canvas.
+/// With `blend` they are laid over what is under them instead of replacing it.
+#[derive(Default, PartialEq, Debug, Clone, Copy)]
+struct RenderSettings {
+    resampling: ResamplingSettings,
+    blend: bool,
+    tone_mapping: ToneMapSettings,
+}
+
 pub struct Packer {
     pub items: Vec<Vec<Item<Pic>>>,
     pub preview_width: f32,
     pub aspect: AspectRatio,
+    pub mode: LayoutMode,
+    pub grid: GridSettings,
+    pub masonry: MasonrySettings,
+    pub treemap: TreemapSettings,
+    pub algorithm: PackingAlgorithm,
     pub equal: bool,
+    pub rotate: bool,
     pub margin: usize,
+    /// Edge pixels of images are repeated this far into margin.
+    pub extrude: usize,
+    /// Cells of packed images start at multiples of it, zero means no alignment.
+    pub align: usize,
+    pub max_iterations: usize,
+    pub optimizer: OptimizerSettings,
+    pub paging: PageSettings,
     pub scale: ImageScaling,
     pub preview: RgbaImage,
     pub actual_size: RectSize,
     pub cached: bool,
+    /// Layout was loaded from file, it is kept until new images are added.
+    pub fixed: bool,
+    /// Transparent borders of images are cut off before packing.
+    pub trim: bool,
+    /// Frames of animated GIF and APNG images are loaded as separate images.
+    pub split_frames: bool,
+    pub channels: ChannelSettings,
+    pub output: OutputSettings,
+    pub resampling: ResamplingSettings,
+    /// Images are scaled by whole numbers only and their pixels are never blended.
+    pub pixel_art: bool,
+    /// Images are composed with alpha over ones under them.
+    pub blend: bool,
+    /// 16-bit sources keep their depth in PNG and TIFF output.
+    pub sixteen_bit: bool,
+    /// How HDR sources are brought to displayable range.
+    pub tone_mapping: ToneMapSettings,
     // pub bg_color: Color32,
-    packing_result: Option<PackingResult>,
+    pages: Vec<PackingResult>,
     cache: HashMap<PathBuf, ImageResult<DynamicImage>>,
+    trims: HashMap<PathBuf, Option<Rect>>,
+    /// Frames of animations in preview, decoded once while it is drawn.
+    animations: HashMap<PathBuf, Vec<(DynamicImage, u32)>>,
 }
 impl Default for Packer {
     fn default() -> Self {
@@ -34,23 +115,53 @@ impl Default for Packer {
             items: Vec::<Vec<Item<Pic>>>::new(),
             preview_width: f32::default(),
             aspect: AspectRatio::Square,
+            mode: LayoutMode::default(),
+            grid: GridSettings::default(),
+            masonry: MasonrySettings::default(),
+            treemap: TreemapSettings::default(),
+            algorithm: PackingAlgorithm::default(),
             equal: false,
+            rotate: false,
             margin: 0,
+            extrude: 0,
+            align: 0,
+            max_iterations: 32,
+            optimizer: OptimizerSettings::default(),
+            paging: PageSettings::default(),
             scale: ImageScaling::default(),
             preview: RgbaImage::new(1, 1),
             actual_size: RectSize::default(),
             // bg_color: Color32::TRANSPARENT,
-            packing_result: None,
+            pages: Vec::<PackingResult>::new(),
             cached: false,
+            fixed: false,
+            trim: false,
+            split_frames: false,
+            channels: ChannelSettings::default(),
+            output: OutputSettings::default(),
+            resampling: ResamplingSettings::default(),
+            pixel_art: false,
+            blend: false,
+            sixteen_bit: false,
+            tone_mapping: ToneMapSettings::default(),
+            trims: HashMap::<PathBuf, Option<Rect>>::new(),
             cache: HashMap::<PathBuf, ImageResult<DynamicImage>>::new(),
+            animations: HashMap::<PathBuf, Vec<(DynamicImage, u32)>>::new(),
         }
     }
 }
 impl Packer {
-    pub fn new(preview_width: f32, aspect: AspectRatio, scale: ImageScaling, equal: bool) -> Self {
+    pub fn new(
+        preview_width: f32,
+        aspect: AspectRatio,
+        algorithm: PackingAlgorithm,
+        scale: ImageScaling,
+        equal: bool,
+    ) -> Self {
         Packer {
             preview_width,
             aspect,
+            algorithm,
             scale,
             equal,
             ..Default::default()
@@ -59,8 +170,13 @@ impl Packer {
 
     pub fn update(&mut self, dropped_items: &[DroppedFile]) -> usize {
         if !dropped_items.is_empty() {
-            let new_pics = load_new_items(dropped_items);
+            let new_pics = load_new_items(dropped_items, self.split_frames);
             if !new_pics.is_empty() {
+                self.fixed = false;
+                //Dropped again files may have changed, their bounds are searched anew
+                for item in &new_pics {
+                    self.trims.remove(&item.data.file);
+                }
                 self.add_items(new_pics);
             }
         }
@@ -72,94 +188,250 @@ impl Packer {
     pub fn undo(&mut self) -> usize {
         if !self.items.is_empty() {
             self.items.pop();
+            self.fixed = false;
             return self.update(&[]);
         }
         0
     }
 
+    /// Sets weight of images ending with `file` path for treemap layout, 1 by default.
+    pub fn set_priority(&mut self, file: &Path, priority: f32) {
+        self.items
+            .iter_mut()
+            .flatten()
+            .filter(|item| item.data.file.ends_with(file))
+            .for_each(|item| item.data.priority = priority);
+    }
+
+    /// Writes packed layout to file, so it can be rendered again without packing.
+    pub fn save_layout(&self, path: &Path) -> io::Result<()> {
+        let pages = self
+            .pages
+            .iter()
+            .map(|packed| SavedPage {
+                total_w: packed.total_w,
+                max_w: packed.max_w,
+                max_h: packed.max_h,
+                images: packed
+                    .positions
+                    .iter()
+                    .map(|(rect, pic)| SavedPic {
+                        file: pic.file.clone(),
+                        original: pic.original,
+                        rect: Frame::from(*rect),
+                        width: pic.width,
+                        height: pic.height,
+                        crop: pic.crop.map(Frame::from),
+                        priority: pic.priority,
+                        rotated: pic.rotated,
+                        animation: pic.animation,
+                    })
+                    .collect(),
+            })
+            .collect();
+        SavedLayout {
+            aspect: self.aspect,
+            mode: self.mode,
+            margin: self.margin,
+            extrude: self.extrude,
+            align: self.align,
+            paging: self.paging,
+            pages,
+        }
+        .save(path)
+    }
+
+    /// Replaces images and their layout with ones saved to file and keeps it `fixed`.
+    /// Images that were moved are searched next to the layout file.
+    pub fn load_layout(&mut self, path: &Path) -> io::Result<usize> {
+        let saved = SavedLayout::open(path)?;
+        let folder = path.parent().unwrap_or_else(|| Path::new(""));
+        self.aspect = saved.aspect;
+        self.mode = saved.mode;
+        self.margin = saved.margin;
+        self.extrude = saved.extrude;
+        self.align = saved.align;
+        self.paging = saved.paging;
+        self.pages = saved
+            .pages
+            .into_iter()
+            .map(|page| PackingResult {
+                total_w: page.total_w,
+                max_w: page.max_w,
+                max_h: page.max_h,
+                positions: page
+                    .images
+                    .into_iter()
+                    .map(|saved| {
+                        let moved = folder.join(saved.file.file_name().unwrap_or_default());
+                        let file = match !saved.file.exists() && moved.is_file() {
+                            true => moved,
+                            false => saved.file,
+                        };
+                        let pic = Pic {
+                            file_size: fs::metadata(&file).map_or(0, |metadata| metadata.len()),
+                            file,
+                            original: saved.original,
+                            width: saved.width,
+                            height: saved.height,
+                            color: random_gray(),
+                            crop: saved.crop.map(|crop| crop.rect()),
+                            priority: saved.priority,
+                            rotated: saved.rotated,
+                            animation: saved.animation,
+                        };
+                        (saved.rect.rect(), pic)
+                    })
+                    .collect(),
+            })
+            .collect();
+        self.items = vec![self
+            .pages
+            .iter()
+            .flat_map(|page| &page.positions)
+            .map(|(rect, pic)| Item::new(pic.clone(), rect.w, rect.h, Rotation::None))
+            .collect()];
+        self.fixed = true;
+        self.cache.clear();
+        self.trims.clear();
+        let num = self.pack();
+        self.combine_thumbnails(0);
+        Ok(num)
+    }
+
     fn add_items(&mut self, new_items: Vec<Item<Pic>>) {
         self.items.push(new_items);
     }
 
     fn pack(&mut self) -> usize {
+        if self.fixed {
+            return self.pages.iter().map(|page| page.positions.len()).sum();
+        }
         if !self.items.is_empty() {
+            let rotation = match self.rotate {
+                true => Rotation::Allowed,
+                false => Rotation::None,
+            };
+            if self.trim {
+                self.find_trims();
+            }
             let mean_max_dim = match self.equal {
                 true => {
-                    self.items
+                    let primary = self.primary_items();
+                    primary
                         .iter()
-                        .flatten()
-                        .map(|item| item.data.width.max(item.data.height))
+                        .map(|item| {
+                            let pic = self.trimmed(item.data.clone());
+                            pic.width.max(pic.height)
+                        })
                         .sum::<u32>()
-                        / (self.items.iter().map(|v| v.len()).sum::<usize>() as u32).max(1)
+                        / (primary.len() as u32).max(1)
                 }
                 false => 0,
             };
             let items_flat: Vec<Item<Pic>> = match self.equal {
                 true => self
-                    .items
-                    .clone()
+                    .primary_items()
                     .into_iter()
-                    .flatten()
                     .map(|item| {
-                        let new_dims =
-                            fit_to_square(item.data.width, item.data.height, mean_max_dim);
+                        let pic = self.trimmed(item.data);
+                        let new_dims = fit_to_square(pic.width, pic.height, mean_max_dim);
+                        let new_dims = match self.pixel_art() {
+                            true => whole_multiple(&pic, new_dims),
+                            false => new_dims,
+                        };
                         Item::new(
                             Pic {
-                                file: item.data.file,
                                 width: new_dims.0,
                                 height: new_dims.1,
-                                color: item.data.color,
+                                ..pic
                             },
-                            new_dims.0 as usize + self.margin,
-                            new_dims.1 as usize + self.margin,
-                            Rotation::None,
+                            self.cell(new_dims.0 as usize),
+                            self.cell(new_dims.1 as usize),
+                            rotation,
                         )
                     })
                     .collect(),
                 false => self
-                    .items
-                    .clone()
+                    .primary_items()
                     .into_iter()
-                    .flatten()
                     .map(|item| {
-                        Item::new(
-                            Pic {
-                                file: item.data.file,
-                                width: item.data.width,
-                                height: item.data.height,
-                                color: item.data.color,
-                            },
-                            item.data.width as usize + self.margin,
-                            item.data.height as usize + self.margin,
-                            Rotation::None,
-                        )
+                        let pic = self.trimmed(item.data);
+                        let (w, h) = (pic.width as usize, pic.height as usize);
+                        Item::new(pic, self.cell(w), self.cell(h), rotation)
                     })
                     .collect(),
             };
 
-            let width = (items_flat.iter().map(|r| r.w * r.h).sum::<usize>() as f32
-                / self.aspect.div())
-            .sqrt();
-            let pic_placement = pack_to_ratio(&items_flat, self.aspect.div(), width, width, 1);
-
-            if let Ok(packed) = pic_placement {
-                let total_w = packed.0;
-                let mut max_w = 0;
-                let mut max_h = 0;
-                let mut positions = Vec::<(Rect, Pic)>::new();
-                for item in packed.2 {
-                    max_w = max_w.max(item.0.w + item.0.x);
-                    max_h = max_h.max(item.0.h + item.0.y);
-                    positions.push(item);
+            let pic_placement = match self.mode {
+                LayoutMode::Packing if self.paged() => pack_pages(
+                    self.algorithm.engine(),
+                    &items_flat,
+                    self.aspect.div(),
+                    &self.paging,
+                    self.align,
+                    self.max_iterations,
+                ),
+                LayoutMode::Packing => {
+                    let width = (items_flat.iter().map(|r| r.w * r.h).sum::<usize>() as f32
+                        / self.aspect.div())
+                    .sqrt();
+                    let (engine, ratio) = (self.algorithm.engine(), self.aspect.div());
+                    //Single attempt takes largest items first
+                    let settings = match self.optimizer.enabled && engine.keeps_order() {
+                        true => self.optimizer,
+                        false => OptimizerSettings {
+                            attempts: 1,
+                            ..self.optimizer
+                        },
+                    };
+                    optimize(&items_flat, ratio, &settings, |items| {
+                        pack_to_ratio(engine, items, ratio, width, self.max_iterations)
+                    })
+                    .map(|packed| vec![packed])
+                }
+                LayoutMode::Justified => justified(&items_flat, self.aspect.div(), self.gutter())
+                    .map(|packed| vec![packed]),
+                LayoutMode::Grid => grid(&items_flat, self.aspect.div(), self.gutter(), &self.grid)
+                    .map(|packed| vec![packed]),
+                LayoutMode::Masonry => {
+                    masonry(&items_flat, self.aspect.div(), self.gutter(), &self.masonry)
+                        .map(|packed| vec![packed])
+                }
+                LayoutMode::Treemap => {
+                    treemap(&items_flat, self.aspect.div(), self.gutter(), &self.treemap)
+                        .map(|packed| vec![packed])
+                }
+                LayoutMode::Frames => {
+                    frames(&items_flat, self.aspect.div(), self.gutter(), &self.grid)
+                        .map(|packed| vec![packed])
+                }
+            };
+
+            match pic_placement {
+                Ok(pages) => {
+                    //Pages keep their own size, others are cropped to content
+                    let paged = self.paged();
+                    self.pages = pages
+                        .into_iter()
+                        .map(|(total_w, height, positions)| {
+                            let (max_w, max_h) = match paged {
+                                true => (total_w, height),
+                                false => bounds(&positions),
+                            };
+                            PackingResult {
+                                total_w,
+                                max_w,
+                                max_h,
+                                positions,
+                            }
+                        })
+                        .collect();
+                }
+                Err(err) => {
+                    println!("Couldn't pack images!\n{}", err);
+                    self.pages.clear();
                 }
-                self.packing_result = Some(PackingResult {
-                    total_w,
-                    max_w,
-                    max_h,
-                    positions,
-                });
-            } else {
-                self.packing_result = None
             }
 
             return items_flat.len();
@@ -167,6 +439,138 @@ impl Packer {
         0
     }
 
+    /// All images except companions of other ones, when channels are enabled.
+    fn primary_items(&self) -> Vec<Item<Pic>> {
+        let files = self.files();
+        self.items
+            .iter()
+            .flatten()
+            .filter(|item| self.channels.primary_of(&item.data.file, &files).is_none())
+            .cloned()
+            .collect()
+    }
+
+    fn files(&self) -> Stems<'_> {
+        Stems::new(
+            self.items
+                .iter()
+                .flatten()
+                .map(|item| item.data.file.as_path()),
+        )
+    }
+
+    /// Same layout with images replaced by their companions with `suffix`.
+    /// Images without companion are left out.
+    fn companion_layout(&self, layout: &LayoutMetadata, suffix: &str) -> LayoutMetadata {
+        let files = self.files();
+        LayoutMetadata {
+            images: layout
+                .images
+                .iter()
+                .filter_map(|placement| {
+                    self.channels
+                        .companion(&placement.file, suffix, &files)
+                        .map(|file| Placement {
+                            file,
+                            ..placement.clone()
+                        })
+                })
+                .collect(),
+            ..layout.clone()
+        }
+    }
+
+    //Opaque bounds are searched once for every file
+    fn find_trims(&mut self) {
+        for item in self.items.iter().flatten() {
+            if !self.trims.contains_key(&item.data.file) {
+                let bounds = open_image(&item.data.file, &self.tone_mapping)
+                    .ok()
+                    .and_then(|image| opaque_bounds(&image));
+                self.trims.insert(item.data.file.clone(), bounds);
+            }
+        }
+    }
+
+    /// Shows only opaque part of image when trimming is on.
+    /// Animation frames keep their canvas, so they don't jump on playback.
+    fn trimmed(&self, pic: Pic) -> Pic {
+        match self.trims.get(&pic.file) {
+            Some(Some(bounds)) if self.trim && pic.animation.is_none() => Pic {
+                width: bounds.w as u32,
+                height: bounds.h as u32,
+                crop: Some(*bounds),
+                ..pic
+            },
+            _ => pic,
+        }
+    }
+
+    /// Whole number sizes are kept only by packing mode, others scale images freely.
+    pub fn pixel_art(&self) -> bool {
+        self.pixel_art && self.mode == LayoutMode::Packing
+    }
+
+    /// Pixel art is always sampled by nearest neighbour.
+    fn resampling(&self) -> ResamplingSettings {
+        match self.pixel_art() {
+            true => ResamplingSettings {
+                filter: Resampling::Nearest,
+                linear: false,
+            },
+            false => self.resampling,
+        }
+    }
+
+    fn rendering(&self) -> RenderSettings {
+        RenderSettings {
+            resampling: self.resampling(),
+            blend: self.blend,
+            tone_mapping: self.tone_mapping,
+        }
+    }
+
+    /// Space between images, it holds margin and extruded edges of both neighbours.
+    fn gutter(&self) -> usize {
+        self.margin + 2 * self.extrude
+    }
+
+    /// Offset of image inside its cell.
+    fn inset(&self) -> usize {
+        match self.mode {
+            LayoutMode::Packing => align_up(self.gutter() / 2, self.align),
+            _ => self.gutter() / 2,
+        }
+    }
+
+    //Side of packed cell for image side, aligned in packing mode
+    fn cell(&self, side: usize) -> usize {
+        match self.mode {
+            LayoutMode::Packing => align_up(
+                self.inset() + side + self.gutter() - self.gutter() / 2,
+                self.align,
+            ),
+            _ => side + self.gutter(),
+        }
+    }
+
+    /// Pages keep actual size, export size is not applied to them.
+    pub fn paged(&self) -> bool {
+        self.mode == LayoutMode::Packing && self.paging.max_size > 0
+    }
+
+    pub fn page_count(&self) -> usize {
+        self.pages.len()
+    }
+
+    //Single page keeps plain name, others get numbered
+    fn page_name(&self, file_name: &str, page: usize) -> String {
+        match self.pages.len() {
+            1 => file_name.to_string(),
+            _ => format!("{}_{}", file_name, page),
+        }
+    }
+
     pub fn combine_thumbnails(&mut self, loaded: isize) {
         let image_size = match loaded {
             0 => RectSize::by_scale_and_ratio(
@@ -179,7 +583,7 @@ impl Packer {
             },
         };
 
-        if let Some(packed) = &self.packing_result {
+        if let Some(packed) = self.pages.first() {
             let crop = (packed.max_w as f32)
                 .max(packed.max_h as f32 / self.aspect.div())
                 .min(packed.total_w as f32);
@@ -190,57 +594,66 @@ impl Packer {
             if loaded == 0 {
                 //Create Layout Preview
                 self.preview = RgbaImage::new(image_size.w as u32, image_size.h as u32);
+                self.animations.clear();
                 for item in &packed.positions {
+                    let (w, h) = match item.1.rotated {
+                        true => (item.1.height, item.1.width),
+                        false => (item.1.width, item.1.height),
+                    };
                     let color_box = RgbaImage::from_pixel(
-                        (item.1.width as f32 * div).floor() as u32,
-                        (item.1.height as f32 * div).floor() as u32,
+                        (w as f32 * div).floor() as u32,
+                        (h as f32 * div).floor() as u32,
                         item.1.color,
                     );
                     let loc = item.0;
                     let (dx, dy) = (
-                        ((loc.x + self.margin / 2) as f32 * div).floor() as u32,
-                        ((loc.y + self.margin / 2) as f32 * div).floor() as u32,
+                        ((loc.x + self.inset()) as f32 * div).floor() as u32,
+                        ((loc.y + self.inset()) as f32 * div).floor() as u32,
                     );
                     replace(&mut self.preview, &color_box, dx, dy);
                 }
             } else {
                 //Update Layout Preview with new loaded image
                 if let Some(item) = &packed.positions.get((loaded - 1) as usize) {
-                    let thumbnail = match self.cached {
-                        true => {
+                    let resampling = self.resampling();
+                    let thumb = |image: &DynamicImage| {
+                        resize(
+                            &visible_region(image, &item.1.original, item.1.region().into()),
+                            (item.1.width as f32 * div).floor() as u32,
+                            (item.1.height as f32 * div).floor() as u32,
+                            &resampling,
+                        )
+                    };
+                    //Frames of animation share the file and are decoded together,
+                    //HDR images follow tone mapping, so they are not cached
+                    let thumbnail = match item.1.animation {
+                        Some(frame) => {
+                            if !self.animations.contains_key(&item.1.file) {
+                                let frames = open_animation(&item.1.file).ok().flatten();
+                                self.animations
+                                    .insert(item.1.file.clone(), frames.unwrap_or_default());
+                            }
+                            self.animations[&item.1.file]
+                                .get(frame.index)
+                                .map(|(image, _)| thumb(image))
+                        }
+                        None if self.cached && !is_high_dynamic_range(&item.1.file) => {
                             if !self.cache.contains_key(&item.1.file) {
                                 self.cache
                                     .insert(item.1.file.clone(), image::open(&item.1.file));
                             }
-                            let stored = self.cache.get(&item.1.file).unwrap();
-                            if let Ok(image) = stored {
-                                Some(thumbnail(
-                                    image,
-                                    (item.1.width as f32 * div).floor() as u32,
-                                    (item.1.height as f32 * div).floor() as u32,
-                                ))
-                            } else {
-                                None
-                            }
-                        }
-                        false => {
-                            let loaded = image::open(&item.1.file);
-                            if let Ok(image) = loaded {
-                                Some(thumbnail(
-                                    &image,
-                                    (item.1.width as f32 * div).floor() as u32,
-                                    (item.1.height as f32 * div).floor() as u32,
-                                ))
-                            } else {
-                                None
-                            }
+                            self.cache[&item.1.file].as_ref().ok().map(thumb)
                         }
+                        None => open_image(&item.1.file, &self.tone_mapping)
+                            .ok()
+                            .map(|image| thumb(&image)),
                     };
                     if let Some(thumbnail) = thumbnail {
+                        let thumbnail = turned(thumbnail, &item.1);
                         let loc = item.0;
                         let (dx, dy) = (
-                            ((loc.x + self.margin / 2) as f32 * div).floor() as u32,
-                            ((loc.y + self.margin / 2) as f32 * div).floor() as u32,
+                            ((loc.x + self.inset()) as f32 * div).floor() as u32,
+                            ((loc.y + self.inset()) as f32 * div).floor() as u32,
                         );
                         replace(&mut self.preview, &thumbnail, dx, dy);
                     }
@@ -251,60 +664,223 @@ impl Packer {
         }
     }
 
-    fn combine_image(&mut self) -> Option<RgbaImage> {
-        if let Some(packed) = &self.packing_result {
-            let max_w = packed.max_w;
-            let max_h = packed.max_h;
-            let image_size = match self.scale {
-                ImageScaling::Actual => RectSize::new(max_w, max_h),
-                ImageScaling::Preview(_) => RectSize::by_scale_and_ratio(
-                    &ImageScaling::Preview(self.preview_width),
-                    &self.aspect,
-                ),
-                scale => RectSize::by_scale_and_ratio(&scale, &self.aspect),
-            };
+    /// Pixel art is never shrunk, so export size smaller than packed images is not kept.
+    pub fn pixel_art_overflows(&self) -> bool {
+        self.pixel_art()
+            && self
+                .pages
+                .iter()
+                .any(|packed| self.export_scale(packed).1 < 1.0)
+    }
 
-            let crop = (packed.max_w as f32)
-                .max(packed.max_h as f32 / self.aspect.div())
-                .min(packed.total_w as f32);
-            let div = match self.scale {
-                ImageScaling::Actual => 1.0,
-                _ => (image_size.w) as f32 / crop,
-            };
+    //Canvas size and factor from packed layout to it
+    fn export_scale(&self, packed: &PackingResult) -> (RectSize, f32) {
+        let scale = match self.paged() {
+            true => ImageScaling::Actual,
+            false => self.scale,
+        };
+        let image_size = match scale {
+            ImageScaling::Actual => RectSize::new(packed.max_w, packed.max_h),
+            ImageScaling::Preview(_) => RectSize::by_scale_and_ratio(
+                &ImageScaling::Preview(self.preview_width),
+                &self.aspect,
+            ),
+            scale => RectSize::by_scale_and_ratio(&scale, &self.aspect),
+        };
+
+        let crop = (packed.max_w as f32)
+            .max(packed.max_h as f32 / self.aspect.div())
+            .min(packed.total_w as f32);
+        let div = match scale {
+            ImageScaling::Actual => 1.0,
+            _ => (image_size.w) as f32 / crop,
+        };
+        (image_size, div)
+    }
 
-            self.actual_size = RectSize::new(max_w, max_h);
-            let mut combined = RgbaImage::new(image_size.w as u32, image_size.h as u32);
+    /// Canvas size and placements of page as it is exported, in output pixels.
+    pub fn page_layout(&self, page: usize) -> Option<LayoutMetadata> {
+        let packed = self.pages.get(page)?;
+        let (image_size, div) = self.export_scale(packed);
+        let (image_size, div) = match self.pixel_art() {
+            true => whole_scale(image_size, div),
+            false => (image_size, div),
+        };
 
-            for item in &packed.positions {
-                if let Ok(image) = image::open(&item.1.file) {
-                    let thumbnail = resize(
-                        &image,
-                        (item.1.width as f32 * div).floor() as u32,
-                        (item.1.height as f32 * div).floor() as u32,
-                        FilterType::CatmullRom,
-                    );
+        let images = packed
+            .positions
+            .iter()
+            .map(|(loc, pic)| {
+                let (w, h) = (
+                    (pic.width as f32 * div).floor() as u32,
+                    (pic.height as f32 * div).floor() as u32,
+                );
+                let region = pic.region();
+                Placement {
+                    file: pic.file.clone(),
+                    original: pic.original,
+                    source: Frame::new(
+                        region.x as u32,
+                        region.y as u32,
+                        region.w as u32,
+                        region.h as u32,
+                    ),
+                    frame: Frame {
+                        x: ((loc.x + self.inset()) as f32 * div).floor() as u32,
+                        y: ((loc.y + self.inset()) as f32 * div).floor() as u32,
+                        w: if pic.rotated { h } else { w },
+                        h: if pic.rotated { w } else { h },
+                    },
+                    rotated: pic.rotated,
+                    animation: pic.animation,
+                }
+            })
+            .collect();
+        Some(LayoutMetadata {
+            image: String::new(),
+            size: image_size,
+            scale: div,
+            extrude: (self.extrude as f32 * div).round() as u32,
+            images,
+        })
+    }
 
-                    let loc = item.0;
-                    let (dx, dy) = (
-                        (loc.x as f32 * div).floor() as u32,
-                        (loc.y as f32 * div).floor() as u32,
+    fn combine_image(&mut self, page: usize) -> Option<DynamicImage> {
+        let layout = self.page_layout(page)?;
+        if let Some(packed) = self.pages.get(page) {
+            self.actual_size = RectSize::new(packed.max_w, packed.max_h);
+        }
+        Some(self.render_image(&layout))
+    }
+
+    //Only PNG and TIFF can hold 16 bits per channel
+    fn deep_output(&self) -> bool {
+        self.sixteen_bit && self.output.format.supports_sixteen_bit()
+    }
+
+    fn render_image(&self, layout: &LayoutMetadata) -> DynamicImage {
+        match self.deep_output() {
+            true => DynamicImage::ImageRgba16(render(layout, &self.rendering())),
+            false => DynamicImage::ImageRgba8(render(layout, &self.rendering())),
+        }
+    }
+
+    //Every suffix gets image named after it, like `Name_n.png`
+    fn export_channels(&self, path: &Path, page_name: &str, page: usize) {
+        if let Some(layout) = self.page_layout(page) {
+            for suffix in self.channels.suffixes() {
+                let companion = self.companion_layout(&layout, suffix);
+                if companion.images.is_empty() {
+                    continue;
+                }
+                if companion.images.len() < layout.images.len() {
+                    println!(
+                        "{} of {} images have no {} companion.",
+                        layout.images.len() - companion.images.len(),
+                        layout.images.len(),
+                        suffix
                     );
-                    replace(&mut combined, &thumbnail, dx, dy);
                 }
+                let img_result = self.output.save(
+                    &self.render_image(&companion),
+                    &Path::new(path).join(format!(
+                        "{}{}.{}",
+                        page_name,
+                        suffix,
+                        self.output.extension()
+                    )),
+                );
+                match img_result {
+                    Ok(_) => println!("Combined {} image saved!", suffix),
+                    Err(err) => println!("Couldn't save image!\n{}", err),
+                }
+            }
+        }
+    }
+
+    //Only GIF can be written, APNG is not supported by encoder
+    fn export_animation(&self, path: &Path, page_name: &str, page: usize) {
+        let layout = self.page_layout(page);
+        let animation = match layout
+            .as_ref()
+            .and_then(|layout| animate(layout, &self.rendering()))
+        {
+            Some(animation) => animation,
+            None => {
+                println!("There are no animated images!");
+                return;
             }
-            return Some(combined);
+        };
+        let gif_result = File::create(Path::new(path).join(format!("{}.{}", page_name, "gif")))
+            .map_err(ImageError::IoError)
+            .and_then(|file| {
+                let mut encoder = GifEncoder::new(file);
+                encoder.set_repeat(Repeat::Infinite)?;
+                encoder.encode_frames(animation.frames().map(|(canvas, delay)| {
+                    AnimationFrame::from_parts(canvas, 0, 0, Delay::from_numer_denom_ms(delay, 1))
+                }))
+            });
+        match gif_result {
+            Ok(_) => println!("Animated image saved!"),
+            Err(err) => println!("Couldn't save animated image!\n{}", err),
         }
-        None
     }
 
-    pub fn export(&mut self, path: &Path, to_zip: bool) {
+    pub fn export(
+        &mut self,
+        path: &Path,
+        to_zip: bool,
+        to_json: bool,
+        to_gif: bool,
+        sheet: Option<SheetFormat>,
+        style: Option<StyleSheet>,
+    ) {
         let file_name = file_timestamp();
-        if let Some(combined) = self.combine_image() {
-            let img_result =
-                combined.save(Path::new(path).join(format!("{}.{}", file_name, "png")));
-            match img_result {
-                Ok(_) => println!("Combined image saved!"),
-                Err(err) => println!("Couldn't save image!\n{}", err),
+        if !self.pages.is_empty() {
+            for page in 0..self.pages.len() {
+                let page_name = self.page_name(&file_name, page);
+                if let Some(combined) = self.combine_image(page) {
+                    let img_result = self.output.save(
+                        &combined,
+                        &Path::new(path).join(format!("{}.{}", page_name, self.output.extension())),
+                    );
+                    match img_result {
+                        Ok(_) => println!("Combined image saved!"),
+                        Err(err) => println!("Couldn't save image!\n{}", err),
+                    }
+                }
+                if self.channels.enabled {
+                    self.export_channels(path, &page_name, page);
+                }
+                if to_gif {
+                    self.export_animation(path, &page_name, page);
+                }
+                if !to_json && sheet.is_none() && style.is_none() {
+                    continue;
+                }
+                if let Some(mut layout) = self.page_layout(page) {
+                    layout.image = format!("{}.{}", page_name, self.output.extension());
+                    if to_json {
+                        let json_result =
+                            layout.save(&Path::new(path).join(format!("{}.{}", page_name, "json")));
+                        match json_result {
+                            Ok(_) => println!("Layout saved!"),
+                            Err(err) => println!("Couldn't save layout!\n{}", err),
+                        }
+                    }
+                    if let Some(sheet) = sheet {
+                        match sheet.save(&layout, self.deep_output(), path) {
+                            Ok(_) => println!("Sprite sheet saved!"),
+                            Err(err) => println!("Couldn't save sprite sheet!\n{}", err),
+                        }
+                    }
+                    if let Some(style) = style {
+                        match style.save(&layout, path) {
+                            Ok(_) => println!("Stylesheet saved!"),
+                            Err(err) => println!("Couldn't save stylesheet!\n{}", err),
+                        }
+                    }
+                }
             }
             if to_zip {
                 let files: Vec<&PathBuf> = self
@@ -326,25 +902,824 @@ impl Packer {
     }
 }
 
+/// Draws every placed image on canvas of layout size.
+/// Animations are decoded once and shared by all their frames.
+fn render<S: Depth>(layout: &LayoutMetadata, settings: &RenderSettings) -> Canvas<S> {
+    let mut combined = Canvas::<S>::new(layout.size.w as u32, layout.size.h as u32);
+    let mut animations = HashMap::<&Path, Vec<(DynamicImage, u32)>>::new();
+    for placement in layout.images.iter().filter(|p| p.animation.is_some()) {
+        if !animations.contains_key(placement.file.as_path()) {
+            let frames = open_animation(&placement.file).ok().flatten();
+            animations.insert(&placement.file, frames.unwrap_or_default());
+        }
+    }
+    for placement in &layout.images {
+        let opened;
+        let image = match placement.animation {
+            Some(frame) => animations
+                .get(placement.file.as_path())
+                .and_then(|frames| frames.get(frame.index))
+                .map(|(image, _)| image),
+            None => {
+                opened = open_image(&placement.file, &settings.tone_mapping).ok();
+                opened.as_ref()
+            }
+        };
+        if let Some(image) = image {
+            let thumbnail = fitted(image, placement, &settings.resampling);
+            draw(
+                &mut combined,
+                &thumbnail,
+                placement,
+                layout.extrude,
+                settings.blend,
+            );
+        }
+    }
+    combined
+}
+
+/// Longest playback of exported animation in milliseconds, when sources don't loop evenly.
+const ANIMATION_LIMIT: u64 = 60_000;
+
+/// Layout with animated sources advanced over time and static ones held.
+/// Frames are drawn one at a time, so only a single canvas is kept while exporting.
+struct Animation<'a> {
+    background: RgbaImage,
+    animated: Vec<(&'a Placement, Vec<(RgbaImage, u32)>)>,
+    changes: Vec<u32>,
+    duration: u32,
+    extrude: u32,
+    blend: bool,
+}
+
+impl Animation<'_> {
+    /// Output frame changes whenever any source does.
+    fn frames(&self) -> impl Iterator<Item = (RgbaImage, u32)> + '_ {
+        self.changes.iter().enumerate().map(move |(i, time)| {
+            let mut canvas = self.background.clone();
+            for (placement, frames) in &self.animated {
+                let mut local = time % animation_length(frames);
+                for (thumbnail, delay) in frames {
+                    if local < *delay {
+                        draw(&mut canvas, thumbnail, placement, self.extrude, self.blend);
+                        break;
+                    }
+                    local -= delay;
+                }
+            }
+            let next = self.changes.get(i + 1).copied().unwrap_or(self.duration);
+            (canvas, next - time)
+        })
+    }
+}
+
+fn animation_length(frames: &[(RgbaImage, u32)]) -> u32 {
+    frames.iter().map(|(_, delay)| delay).sum()
+}
+
+//Common multiple of lengths lets every source loop seamlessly.
+//If it is longer than limit, output ends with the longest source and shorter ones jump on loop.
+fn loop_length(lengths: &[u32]) -> u32 {
+    let longest = lengths.iter().copied().max().unwrap_or_default();
+    let limit = ANIMATION_LIMIT.max(longest as u64);
+    let mut common = 1u64;
+    for length in lengths.iter().map(|length| *length as u64) {
+        let (mut a, mut b) = (common, length);
+        while b > 0 {
+            (a, b) = (b, a % b);
+        }
+        common = common / a.max(1) * length;
+        if common > limit {
+            return longest;
+        }
+    }
+    common as u32
+}
+
+/// Prepares animation of layout, `None` if no source is animated.
+fn animate<'a>(layout: &'a LayoutMetadata, settings: &RenderSettings) -> Option<Animation<'a>> {
+    let (mut animated, mut held) = (Vec::new(), Vec::new());
+    for placement in &layout.images {
+        let frames = match placement.animation {
+            Some(_) => None,
+            None => open_animation(&placement.file).ok().flatten(),
+        };
+        match frames {
+            Some(frames) => {
+                //Browsers show too short delays as 100 ms
+                let frames: Vec<(RgbaImage, u32)> = frames
+                    .iter()
+                    .map(|(image, delay)| {
+                        let delay = match delay {
+                            0..=10 => 100,
+                            delay => *delay,
+                        };
+                        (fitted(image, placement, &settings.resampling), delay)
+                    })
+                    .collect();
+                animated.push((placement, frames));
+            }
+            None => held.push(placement.clone()),
+        }
+    }
+    if animated.is_empty() {
+        return None;
+    }
+    let background = render(
+        &LayoutMetadata {
+            images: held,
+            ..layout.clone()
+        },
+        settings,
+    );
+
+    let lengths: Vec<u32> = animated
+        .iter()
+        .map(|(_, frames)| animation_length(frames))
+        .collect();
+    let duration = loop_length(&lengths);
+    let mut changes = vec![0];
+    for (_, frames) in &animated {
+        let mut time = 0;
+        for (_, delay) in frames.iter().cycle() {
+            time += delay;
+            if time >= duration {
+                break;
+            }
+            changes.push(time);
+        }
+    }
+    changes.sort_unstable();
+    changes.dedup();
+
+    Some(Animation {
+        background,
+        animated,
+        changes,
+        duration,
+        extrude: layout.extrude,
+        blend: settings.blend,
+    })
+}
+
+/// Channel type of rendered canvas.
+trait Depth: Primitive + 'static {
+    fn convert(image: &DynamicImage) -> Canvas<Self>;
+}
+
+impl Depth for u8 {
+    fn convert(image: &DynamicImage) -> Canvas<Self> {
+        image.to_rgba8()
+    }
+}
+
+impl Depth for u16 {
+    fn convert(image: &DynamicImage) -> Canvas<Self> {
+        image.to_rgba16()
+    }
+}
+
+/// Shown part of source resized to its frame and turned if needed.
+fn fitted<S: Depth>(
+    image: &DynamicImage,
+    placement: &Placement,
+    resampling: &ResamplingSettings,
+) -> Canvas<S> {
+    let (w, h) = placement.unturned();
+    let thumbnail = resize(
+        &visible_region(&S::convert(image), &placement.original, placement.source),
+        w,
+        h,
+        resampling,
+    );
+    match placement.rotated {
+        true => rotate90(&thumbnail),
+        false => thumbnail,
+    }
+}
+
+fn draw<S: Depth>(
+    canvas: &mut Canvas<S>,
+    thumbnail: &Canvas<S>,
+    placement: &Placement,
+    pixels: u32,
+    blend: bool,
+) {
+    let frame = placement.frame;
+    match blend {
+        true => lay_over(canvas, thumbnail, frame.x, frame.y),
+        false => replace(canvas, thumbnail, frame.x, frame.y),
+    }
+    if pixels > 0 {
+        extrude(canvas, frame, pixels);
+    }
+}
+
+/// Alpha compositing of `image` over `canvas` at `x`, `y`.
+/// Opaque canvas stays opaque, which blending of `image` crate doesn't keep.
+fn lay_over<S: Depth>(canvas: &mut Canvas<S>, image: &Canvas<S>, x: u32, y: u32) {
+    let max = S::max_value().to_f32().unwrap_or(255.0);
+    let (w, h) = (
+        image.width().min(canvas.width().saturating_sub(x)),
+        image.height().min(canvas.height().saturating_sub(y)),
+    );
+    for (dx, dy) in (0..h).flat_map(|dy| (0..w).map(move |dx| (dx, dy))) {
+        let top = image.get_pixel(dx, dy);
+        let bottom = canvas.get_pixel_mut(x + dx, y + dy);
+        let level = |value: S| value.to_f32().unwrap_or(0.0) / max;
+        let (top_alpha, bottom_alpha) = (level(top[3]), level(bottom[3]));
+        let alpha = top_alpha + bottom_alpha * (1.0 - top_alpha);
+        if alpha <= 0.0 {
+            continue;
+        }
+        let quantize =
+            |value: f32| S::from((value.clamp(0.0, 1.0) * max).round()).unwrap_or(S::max_value());
+        for i in 0..3 {
+            let color = (level(top[i]) * top_alpha
+                + level(bottom[i]) * bottom_alpha * (1.0 - top_alpha))
+                / alpha;
+            bottom[i] = quantize(color);
+        }
+        bottom[3] = quantize(alpha);
+    }
+}
+
+/// Repeats edge pixels of `frame` outwards by `pixels`, corners included.
+fn extrude<S: Depth>(canvas: &mut Canvas<S>, frame: Frame, pixels: u32) {
+    if frame.w == 0 || frame.h == 0 {
+        return;
+    }
+    let (right, bottom) = (
+        (frame.x + frame.w + pixels).min(canvas.width()),
+        (frame.y + frame.h + pixels).min(canvas.height()),
+    );
+    for y in frame.y.saturating_sub(pixels)..bottom {
+        for x in frame.x.saturating_sub(pixels)..right {
+            let inside = (frame.x..frame.x + frame.w).contains(&x)
+                && (frame.y..frame.y + frame.h).contains(&y);
+            if !inside {
+                let edge = *canvas.get_pixel(
+                    x.clamp(frame.x, frame.x + frame.w - 1),
+                    y.clamp(frame.y, frame.y + frame.h - 1),
+                );
+                canvas.put_pixel(x, y, edge);
+            }
+        }
+    }
+}
+
+fn align_up(value: usize, align: usize) -> usize {
+    match align {
+        0 | 1 => value,
+        _ => value.div_ceil(align) * align,
+    }
+}
+
+//Source may be retouched after packing, so region follows its new size
+fn visible_region<'a, I: GenericImageView>(
+    image: &'a I,
+    original: &RectSize,
+    region: Frame,
+) -> SubImage<&'a I> {
+    let (kx, ky) = (
+        image.width() as f32 / original.w.max(1) as f32,
+        image.height() as f32 / original.h.max(1) as f32,
+    );
+    crop_imm(
+        image,
+        (region.x as f32 * kx) as u32,
+        (region.y as f32 * ky) as u32,
+        ((region.w as f32 * kx) as u32).max(1),
+        ((region.h as f32 * ky) as u32).max(1),
+    )
+}
+
+/// Canvas size for the largest whole scale below `div`, but not below actual size.
+/// Canvas follows whole scale, so it keeps the same margins around images.
+fn whole_scale(size: RectSize, div: f32) -> (RectSize, f32) {
+    let whole = div.floor().max(1.0);
+    (
+        RectSize::new(
+            (size.w as f32 * whole / div).ceil() as usize,
+            (size.h as f32 * whole / div).ceil() as usize,
+        ),
+        whole,
+    )
+}
+
+/// Size of `pic` snapped to the largest whole multiple of its region that fits `dims`.
+/// Images are never shrunk below their region.
+fn whole_multiple(pic: &Pic, dims: (u32, u32)) -> (u32, u32) {
+    let region = pic.region();
+    let (w, h) = (region.w.max(1) as u32, region.h.max(1) as u32);
+    let k = (dims.0 / w).min(dims.1 / h).max(1);
+    (w * k, h * k)
+}
+
+fn bounds(positions: &[(Rect, Pic)]) -> (usize, usize) {
+    positions.iter().fold((0, 0), |(max_w, max_h), (rect, _)| {
+        (max_w.max(rect.right()), max_h.max(rect.bottom()))
+    })
+}
+
+fn turned(image: RgbaImage, pic: &Pic) -> RgbaImage {
+    match pic.rotated {
+        true => rotate90(&image),
+        false => image,
+    }
+}
+
+/// Finds the narrowest area of given `ratio` that fits all items.
+/// Grows `width` exponentially until everything fits, then bisects down to 1% precision.
 fn pack_to_ratio(
+    engine: &dyn LayoutEngine,
     items: &[Item<Pic>],
     ratio: f32,
     width: f32,
-    original_width: f32,
-    step: usize,
-) -> Result<(usize, usize, PackedItems<Pic>), ()> {
-    let height = width * ratio;
-    let rect = Rect::of_size(width as usize, height as usize);
-    let packed = pack(rect, items.to_owned());
-    if let Ok(packed_items) = packed {
-        Ok((width as usize, height as usize, packed_items))
-    } else {
-        pack_to_ratio(
-            items,
-            ratio,
-            original_width + original_width * step as f32 * 0.01, // add 1% per step
-            original_width,
-            step + 1,
+    max_iterations: usize,
+) -> Result<Packed, PackingError> {
+    let try_pack = |width: f32| {
+        let (w, h) = (width as usize, (width * ratio) as usize);
+        engine
+            .pack(Rect::of_size(w, h), items)
+            .ok()
+            .map(|positions| (w, h, positions))
+    };
+
+    let mut iterations = 0;
+    let mut low = width.max(1.0);
+    let mut high = low;
+    let mut packed = None;
+    while packed.is_none() {
+        if iterations >= max_iterations {
+            return Err(PackingError::DoesNotFit(iterations));
+        }
+        iterations += 1;
+        packed = try_pack(high);
+        if packed.is_none() {
+            low = high;
+            high *= 2.0;
+        }
+    }
+    while high - low > high * 0.01 && iterations < max_iterations {
+        iterations += 1;
+        let middle = (low + high) / 2.0;
+        match try_pack(middle) {
+            Some(fitted) => {
+                high = middle;
+                packed = Some(fitted);
+            }
+            None => low = middle,
+        }
+    }
+    packed.ok_or(PackingError::DoesNotFit(iterations))
+}
+
+/// Failed attempts to put another item into gaps of a page before it is closed.
+const GAP_ATTEMPTS: usize = 32;
+
+/// Spreads items over pages no larger than `settings.side()`, in order of size.
+/// Images larger than a page are shrunk to fit it. The last page is packed to ratio.
+fn pack_pages(
+    engine: &dyn LayoutEngine,
+    items: &[Item<Pic>],
+    ratio: f32,
+    settings: &PageSettings,
+    align: usize,
+    max_iterations: usize,
+) -> Result<Vec<Packed>, PackingError> {
+    let max = settings.side() as f32;
+    let page = match ratio <= 1.0 {
+        true => Rect::of_size(max as usize, (max * ratio).max(1.0) as usize),
+        false => Rect::of_size((max / ratio).max(1.0) as usize, max as usize),
+    };
+    let mut rest: Vec<Item<Pic>> = items
+        .iter()
+        .map(|item| fit_to_page(item, &page, align))
+        .collect();
+    ItemOrder::Priority.apply(&mut rest);
+
+    let mut pages = Vec::<Packed>::new();
+    while !rest.is_empty() {
+        let (mut positions, count) = engine.pack_some(page, &rest);
+        if count == 0 {
+            return Err(PackingError::PageTooSmall);
+        }
+        //Fill gaps with smaller items from the rest.
+        //Items larger than free area are skipped without packing, failed attempts are limited.
+        let mut chosen: Vec<Item<Pic>> = rest.drain(..count).collect();
+        let mut free = (page.w * page.h).saturating_sub(chosen.iter().map(|r| r.w * r.h).sum());
+        let (mut i, mut failures) = (0, 0);
+        while i < rest.len() && failures < GAP_ATTEMPTS {
+            let area = rest[i].w * rest[i].h;
+            if area > free {
+                i += 1;
+                continue;
+            }
+            chosen.push(rest[i].clone());
+            match engine.pack(page, &chosen) {
+                Ok(more) => {
+                    positions = more;
+                    free -= area;
+                    rest.remove(i);
+                }
+                Err(()) => {
+                    chosen.pop();
+                    failures += 1;
+                    i += 1;
+                }
+            }
+        }
+        //Last page doesn't have to be full size
+        if rest.is_empty() {
+            let width = (chosen.iter().map(|r| r.w * r.h).sum::<usize>() as f32 / ratio).sqrt();
+            if let Ok((_, _, compact)) =
+                pack_to_ratio(engine, &chosen, ratio, width, max_iterations)
+            {
+                let (w, h) = bounds(&compact);
+                if w <= page.w && h <= page.h {
+                    positions = compact;
+                }
+            }
+        }
+        let (w, h) = bounds(&positions);
+        //Page side is power of two itself, so rounding up never passes it
+        let (w, h) = match settings.power_of_two {
+            true => (w.next_power_of_two(), h.next_power_of_two()),
+            false => (w, h),
+        };
+        pages.push((w, h, positions));
+    }
+    Ok(pages)
+}
+
+fn fit_to_page(item: &Item<Pic>, page: &Rect, align: usize) -> Item<Pic> {
+    if item.w <= page.w && item.h <= page.h {
+        return item.clone();
+    }
+    let (margin_w, margin_h) = (
+        item.w - item.data.width as usize,
+        item.h - item.data.height as usize,
+    );
+    let (free_w, free_h) = (
+        page.w.saturating_sub(margin_w).max(1),
+        page.h.saturating_sub(margin_h).max(1),
+    );
+    //Aligned cell stays aligned after shrinking
+    let snap = |side: usize, margin: usize| match align {
+        0 | 1 => side,
+        _ => ((side + margin) / align * align)
+            .saturating_sub(margin)
+            .max(1),
+    };
+    let k = (free_w as f32 / item.data.width as f32).min(free_h as f32 / item.data.height as f32);
+    let (w, h) = (
+        snap(
+            ((item.data.width as f32 * k) as usize).clamp(1, free_w),
+            margin_w,
+        ),
+        snap(
+            ((item.data.height as f32 * k) as usize).clamp(1, free_h),
+            margin_h,
+        ),
+    );
+    Item::new(
+        Pic {
+            width: w as u32,
+            height: h as u32,
+            ..item.data.clone()
+        },
+        w + margin_w,
+        h + margin_h,
+        item.rot,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layout::PackingAlgorithm;
+    use crate::loader::AnimationFrame;
+    use crate::loader::{assert_apart, test_items};
+    use crate::metadata::SavedLayout;
+    use crunch::Rect;
+    use image::Rgba;
+
+    #[test]
+    fn pack_to_ratio_stops_at_iteration_cap() {
+        let engine = PackingAlgorithm::Crunch.engine();
+        let big = [Item::new(Pic::blank(100, 100), 100, 100, Rotation::None)];
+        assert!(matches!(
+            pack_to_ratio(engine, &big, 1.0, 1.0, 3),
+            Err(PackingError::DoesNotFit(3))
+        ));
+        assert!(matches!(
+            pack_to_ratio(engine, &big, 1.0, 1.0, 0),
+            Err(PackingError::DoesNotFit(0))
+        ));
+    }
+
+    #[test]
+    fn pack_to_ratio_keeps_ratio() {
+        for algorithm in PackingAlgorithm::ALL {
+            let (w, h, positions) = pack_to_ratio(
+                algorithm.engine(),
+                &test_items(30, Rotation::None),
+                0.5,
+                10.0,
+                100,
+            )
+            .unwrap();
+            assert_eq!(positions.len(), 30);
+            assert_eq!(h, (w as f32 * 0.5) as usize);
+            let (right, bottom) = bounds(&positions);
+            assert!(right <= w && bottom <= h);
+        }
+    }
+
+    #[test]
+    fn pages_hold_every_item_apart() {
+        let settings = PageSettings {
+            max_size: 96,
+            power_of_two: false,
+        };
+        for algorithm in PackingAlgorithm::ALL {
+            let items = test_items(60, Rotation::None);
+            let pages = pack_pages(algorithm.engine(), &items, 1.0, &settings, 0, 100).unwrap();
+            assert!(pages.len() > 1);
+            assert_eq!(pages.iter().map(|page| page.2.len()).sum::<usize>(), 60);
+            for (w, h, positions) in &pages {
+                assert_apart(Rect::of_size(*w, *h), positions);
+            }
+        }
+    }
+
+    #[test]
+    fn power_of_two_pages_stay_within_max_size() {
+        let settings = PageSettings {
+            max_size: 100,
+            power_of_two: true,
+        };
+        assert_eq!(settings.side(), 64);
+        let items = test_items(60, Rotation::None);
+        let pages = pack_pages(
+            PackingAlgorithm::MaxRects.engine(),
+            &items,
+            1.0,
+            &settings,
+            0,
+            100,
         )
+        .unwrap();
+        for (w, h, positions) in &pages {
+            assert!(w.is_power_of_two() && h.is_power_of_two());
+            assert!(*w <= 64 && *h <= 64);
+            assert_apart(Rect::of_size(*w, *h), positions);
+        }
+    }
+
+    #[test]
+    fn large_items_are_fitted_to_aligned_page() {
+        let page = Rect::of_size(64, 64);
+        //Cell holds 6 pixels of margin around image
+        let item = Item::new(Pic::blank(300, 150), 306, 156, Rotation::None);
+        for align in [0, 4] {
+            let fitted = fit_to_page(&item, &page, align);
+            assert!(fitted.w <= page.w && fitted.h <= page.h);
+            assert_eq!(fitted.w - fitted.data.width as usize, 6);
+            assert_eq!(fitted.h - fitted.data.height as usize, 6);
+            if align > 0 {
+                assert_eq!(fitted.w % align, 0);
+                assert_eq!(fitted.h % align, 0);
+            }
+        }
+        let small = Item::new(Pic::blank(20, 10), 26, 16, Rotation::None);
+        assert_eq!(fit_to_page(&small, &page, 4).w, 26);
+    }
+
+    #[test]
+    fn json_keeps_trimmed_rotated_sizes() {
+        let mut packer = Packer {
+            scale: ImageScaling::Actual,
+            margin: 2,
+            ..Default::default()
+        };
+        //Shown part is 20x12 of 40x30 source, turned in its cell
+        let pic = Pic {
+            crop: Some(Rect::new(4, 6, 20, 12)),
+            original: RectSize::new(40, 30),
+            rotated: true,
+            ..Pic::blank(20, 12)
+        };
+        packer.pages = vec![PackingResult {
+            total_w: 60,
+            max_w: 60,
+            max_h: 60,
+            positions: vec![(Rect::new(10, 20, 14, 22), pic)],
+        }];
+        let json = serde_json::to_value(packer.page_layout(0).unwrap()).unwrap();
+        let placement = &json["images"][0];
+        assert_eq!(placement["original"], serde_json::json!({"w": 40, "h": 30}));
+        assert_eq!(
+            placement["source"],
+            serde_json::json!({"x": 4, "y": 6, "w": 20, "h": 12})
+        );
+        assert_eq!(
+            placement["frame"],
+            serde_json::json!({"x": 11, "y": 21, "w": 12, "h": 20})
+        );
+        assert_eq!(placement["rotated"], true);
+    }
+
+    #[test]
+    fn layout_file_keeps_placements() {
+        let mut packer = Packer {
+            margin: 2,
+            extrude: 1,
+            align: 4,
+            ..Default::default()
+        };
+        let trimmed = Pic {
+            crop: Some(Rect::new(3, 5, 20, 12)),
+            original: RectSize::new(30, 20),
+            rotated: true,
+            ..Pic::blank(20, 12)
+        };
+        let frame = Pic {
+            file: PathBuf::from("walk.gif"),
+            animation: Some(AnimationFrame {
+                index: 2,
+                delay: 80,
+            }),
+            priority: 2.5,
+            ..Pic::blank(16, 16)
+        };
+        packer.pages = vec![PackingResult {
+            total_w: 64,
+            max_w: 48,
+            max_h: 40,
+            positions: vec![
+                (Rect::new(0, 0, 16, 24), trimmed),
+                (Rect::new(16, 0, 20, 20), frame),
+            ],
+        }];
+        let path = std::env::temp_dir().join("ppp_round_trip.ppp");
+        packer.save_layout(&path).unwrap();
+
+        let mut loaded = Packer::default();
+        assert_eq!(loaded.load_layout(&path).unwrap(), 2);
+        assert_eq!((loaded.margin, loaded.extrude, loaded.align), (2, 1, 4));
+        let (page, saved) = (&loaded.pages[0], &packer.pages[0]);
+        assert_eq!((page.total_w, page.max_w, page.max_h), (64, 48, 40));
+        for ((rect, pic), (saved_rect, saved_pic)) in page.positions.iter().zip(&saved.positions) {
+            assert_eq!(rect, saved_rect);
+            assert_eq!(pic.file, saved_pic.file);
+            assert_eq!(
+                (pic.original.w, pic.original.h),
+                (saved_pic.original.w, saved_pic.original.h)
+            );
+            assert_eq!((pic.width, pic.height), (saved_pic.width, saved_pic.height));
+            assert_eq!(pic.crop, saved_pic.crop);
+            assert_eq!(pic.priority, saved_pic.priority);
+            assert_eq!(pic.rotated, saved_pic.rotated);
+            assert_eq!(pic.animation, saved_pic.animation);
+        }
+    }
+
+    #[test]
+    fn empty_layout_file_is_refused() {
+        let path = std::env::temp_dir().join("ppp_empty.ppp");
+        Packer::default().save_layout(&path).unwrap();
+        let err = Packer::default().load_layout(&path).unwrap_err();
+        assert_eq!(err.to_string(), PackingError::NoImages.to_string());
+        assert!(SavedLayout::open(&path).is_err());
+    }
+
+    #[test]
+    fn aligns_up_to_multiple() {
+        assert_eq!(align_up(13, 0), 13);
+        assert_eq!(align_up(13, 1), 13);
+        assert_eq!(align_up(13, 4), 16);
+        assert_eq!(align_up(16, 4), 16);
+    }
+
+    #[test]
+    fn aligned_cells_hold_gutter() {
+        let packer = Packer {
+            mode: LayoutMode::Packing,
+            margin: 2,
+            extrude: 1,
+            align: 8,
+            ..Default::default()
+        };
+        assert_eq!(packer.gutter(), 4);
+        assert_eq!(packer.inset(), 8);
+        //Image starts at aligned offset and keeps half of gutter after it
+        assert_eq!(packer.cell(13), 24);
+        assert_eq!(packer.cell(14), 24);
+        assert_eq!(packer.cell(15), 32);
+    }
+
+    #[test]
+    fn extrude_repeats_edges() {
+        let mut canvas = Canvas::<u8>::new(6, 6);
+        canvas.put_pixel(2, 2, Rgba([10, 0, 0, 255]));
+        canvas.put_pixel(3, 2, Rgba([20, 0, 0, 255]));
+        canvas.put_pixel(2, 3, Rgba([30, 0, 0, 255]));
+        canvas.put_pixel(3, 3, Rgba([40, 0, 0, 255]));
+        extrude(&mut canvas, Frame::new(2, 2, 2, 2), 2);
+        assert_eq!(canvas.get_pixel(0, 0)[0], 10);
+        assert_eq!(canvas.get_pixel(5, 0)[0], 20);
+        assert_eq!(canvas.get_pixel(0, 5)[0], 30);
+        assert_eq!(canvas.get_pixel(5, 5)[0], 40);
+        assert_eq!(canvas.get_pixel(1, 3)[0], 30);
+        assert_eq!(canvas.get_pixel(3, 1)[0], 20);
+    }
+
+    #[test]
+    fn loop_length_is_common_multiple_within_limit() {
+        assert_eq!(loop_length(&[900, 400]), 3600);
+        assert_eq!(loop_length(&[900, 450]), 900);
+        assert_eq!(loop_length(&[59_999, 59_998]), 59_999);
+    }
+
+    #[test]
+    fn whole_scale_rounds_down_but_not_below_actual_size() {
+        let (size, div) = whole_scale(RectSize::new(100, 50), 2.5);
+        assert_eq!((size.w, size.h, div), (80, 40, 2.0));
+        let (size, div) = whole_scale(RectSize::new(100, 50), 0.5);
+        assert_eq!((size.w, size.h, div), (200, 100, 1.0));
+    }
+
+    #[test]
+    fn whole_multiple_fits_region() {
+        let pic = Pic {
+            crop: Some(Rect::new(2, 2, 10, 6)),
+            ..Pic::blank(10, 6)
+        };
+        assert_eq!(whole_multiple(&pic, (35, 30)), (30, 18));
+        assert_eq!(whole_multiple(&pic, (40, 18)), (30, 18));
+        //Never shrunk below the region
+        assert_eq!(whole_multiple(&pic, (5, 5)), (10, 6));
+    }
+
+    #[test]
+    fn small_export_keeps_pixel_art_actual_size() {
+        let mut packer = Packer {
+            mode: LayoutMode::Packing,
+            pixel_art: true,
+            scale: ImageScaling::HalfK,
+            ..Default::default()
+        };
+        packer.pages = vec![PackingResult {
+            total_w: 1000,
+            max_w: 1000,
+            max_h: 1000,
+            positions: vec![(Rect::new(0, 0, 1000, 1000), Pic::blank(1000, 1000))],
+        }];
+        assert!(packer.pixel_art_overflows());
+        let layout = packer.page_layout(0).unwrap();
+        assert_eq!((layout.size.w, layout.scale), (1000, 1.0));
+        assert_eq!(layout.images[0].frame, Frame::new(0, 0, 1000, 1000));
+
+        packer.scale = ImageScaling::FourK;
+        assert!(!packer.pixel_art_overflows());
+        assert_eq!(packer.page_layout(0).unwrap().scale, 4.0);
+    }
+
+    #[test]
+    fn blend_lays_images_over_canvas() {
+        let placement = Placement {
+            file: PathBuf::new(),
+            original: RectSize::new(2, 1),
+            source: Frame::new(0, 0, 2, 1),
+            frame: Frame::new(1, 1, 2, 1),
+            rotated: false,
+            animation: None,
+        };
+        let thumbnail = Canvas::<u8>::from_fn(2, 1, |x, _| match x {
+            0 => Rgba([0, 0, 255, 128]),
+            _ => Rgba([0, 0, 255, 0]),
+        });
+        let red = Rgba([255, 0, 0, 255]);
+
+        let mut blended = Canvas::<u8>::from_pixel(4, 3, red);
+        draw(&mut blended, &thumbnail, &placement, 0, true);
+        let mixed = blended.get_pixel(1, 1);
+        assert!((126..=128).contains(&mixed[0]) && (127..=129).contains(&mixed[2]));
+        assert_eq!(mixed[3], 255);
+        //Transparent pixels leave canvas as it was
+        assert_eq!(blended.get_pixel(2, 1), &red);
+
+        let mut empty = Canvas::<u8>::new(4, 3);
+        draw(&mut empty, &thumbnail, &placement, 0, true);
+        assert_eq!(empty.get_pixel(1, 1), &Rgba([0, 0, 255, 128]));
+
+        let mut replaced = Canvas::<u8>::from_pixel(4, 3, red);
+        draw(&mut replaced, &thumbnail, &placement, 0, false);
+        assert_eq!(replaced.get_pixel(1, 1), &Rgba([0, 0, 255, 128]));
+        assert_eq!(replaced.get_pixel(2, 1), &Rgba([0, 0, 255, 0]));
+        assert_eq!(replaced.get_pixel(0, 1), &red);
     }
 }
@@ -0,0 +1,196 @@
+use image::imageops::{self, FilterType};
+use image::{GenericImageView, ImageBuffer, Primitive, Rgba};
+use serde::{Deserialize, Serialize};
+
+/// Image with channels of type `S`, like `u8` or `u16`.
+pub type Canvas<S> = ImageBuffer<Rgba<S>, Vec<S>>;
+
+/// Filters used to resize images to their place in package.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum Resampling {
+    Nearest,
+    Triangle,
+    #[default]
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl Resampling {
+    pub const ALL: [Resampling; 5] = [
+        Resampling::Nearest,
+        Resampling::Triangle,
+        Resampling::CatmullRom,
+        Resampling::Gaussian,
+        Resampling::Lanczos3,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Resampling::Nearest => "Nearest",
+            Resampling::Triangle => "Triangle",
+            Resampling::CatmullRom => "Catmull-Rom",
+            Resampling::Gaussian => "Gaussian",
+            Resampling::Lanczos3 => "Lanczos3",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Resampling::ALL
+            .iter()
+            .find(|filter| {
+                filter
+                    .name()
+                    .replace('-', "")
+                    .eq_ignore_ascii_case(&name.trim().replace('-', ""))
+            })
+            .copied()
+    }
+
+    fn filter_type(&self) -> FilterType {
+        match self {
+            Resampling::Nearest => FilterType::Nearest,
+            Resampling::Triangle => FilterType::Triangle,
+            Resampling::CatmullRom => FilterType::CatmullRom,
+            Resampling::Gaussian => FilterType::Gaussian,
+            Resampling::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// With `linear` colors are blended in linear light instead of sRGB,
+/// so downscaled images keep their brightness.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ResamplingSettings {
+    pub filter: Resampling,
+    pub linear: bool,
+}
+
+/// Resizes `image` to exactly `w` x `h` pixels, keeping its bit depth.
+/// Colors are weighted by alpha, so transparent pixels don't leak dark fringes.
+pub fn resize<I, S>(image: &I, w: u32, h: u32, settings: &ResamplingSettings) -> Canvas<S>
+where
+    I: GenericImageView<Pixel = Rgba<S>>,
+    S: Primitive + 'static,
+{
+    let filter = settings.filter.filter_type();
+    let max = S::max_value();
+    let opaque = image.pixels().all(|(_, _, pixel)| pixel[3] == max);
+    if settings.filter == Resampling::Nearest || (opaque && !settings.linear) {
+        return imageops::resize(image, w, h, filter);
+    }
+    let (levels, scale) = (
+        max.to_usize().unwrap_or(255) + 1,
+        max.to_f32().unwrap_or(255.0),
+    );
+    let decode: Vec<f32> = (0..levels)
+        .map(|v| match settings.linear {
+            true => srgb_to_linear(v as f32 / scale),
+            false => v as f32 / scale,
+        })
+        .collect();
+    let level = |value: S| decode[value.to_usize().unwrap_or(0)];
+    let (source_w, source_h) = image.dimensions();
+    let premultiplied = ImageBuffer::<Rgba<f32>, Vec<f32>>::from_fn(source_w, source_h, |x, y| {
+        let pixel = image.get_pixel(x, y);
+        let alpha = pixel[3].to_f32().unwrap_or(0.0) / scale;
+        Rgba([
+            level(pixel[0]) * alpha,
+            level(pixel[1]) * alpha,
+            level(pixel[2]) * alpha,
+            alpha,
+        ])
+    });
+    let resized = imageops::resize(&premultiplied, w, h, filter);
+    let quantize = |value: f32| S::from((value.clamp(0.0, 1.0) * scale).round()).unwrap_or(max);
+    let encode = |value: f32, alpha: f32| {
+        let value = match alpha > 0.0 {
+            true => (value / alpha).clamp(0.0, 1.0),
+            false => 0.0,
+        };
+        match settings.linear {
+            true => quantize(linear_to_srgb(value)),
+            false => quantize(value),
+        }
+    };
+    Canvas::<S>::from_fn(w, h, |x, y| {
+        let pixel = resized.get_pixel(x, y);
+        let alpha = pixel[3].clamp(0.0, 1.0);
+        Rgba([
+            encode(pixel[0], alpha),
+            encode(pixel[1], alpha),
+            encode(pixel[2], alpha),
+            quantize(alpha),
+        ])
+    })
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    match value <= 0.04045 {
+        true => value / 12.92,
+        false => ((value + 0.055) / 1.055).powf(2.4),
+    }
+}
+
+pub fn linear_to_srgb(value: f32) -> f32 {
+    match value <= 0.0031308 {
+        true => value * 12.92,
+        false => 1.055 * value.powf(1.0 / 2.4) - 0.055,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    #[test]
+    fn downscaled_edges_have_no_dark_fringe() {
+        //White half next to transparent black
+        let image = RgbaImage::from_fn(16, 16, |x, _| match x < 8 {
+            true => Rgba([255, 255, 255, 255]),
+            false => Rgba([0, 0, 0, 0]),
+        });
+        for linear in [false, true] {
+            for filter in [
+                Resampling::Triangle,
+                Resampling::CatmullRom,
+                Resampling::Lanczos3,
+            ] {
+                let settings = ResamplingSettings { filter, linear };
+                let resized: Canvas<u8> = resize(&image, 5, 5, &settings);
+                assert!(resized.pixels().any(|pixel| (1..255).contains(&pixel[3])));
+                for pixel in resized.pixels().filter(|pixel| pixel[3] > 0) {
+                    assert!(
+                        pixel[0] == 255 && pixel[1] == 255 && pixel[2] == 255,
+                        "{:?}",
+                        pixel
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn linear_downscale_keeps_brightness() {
+        let image = RgbaImage::from_fn(16, 16, |x, y| match (x + y) % 2 {
+            0 => Rgba([255, 255, 255, 255]),
+            _ => Rgba([0, 0, 0, 255]),
+        });
+        let mean_light = |linear: bool| {
+            let settings = ResamplingSettings {
+                filter: Resampling::Triangle,
+                linear,
+            };
+            let resized: Canvas<u8> = resize(&image, 4, 4, &settings);
+            resized
+                .pixels()
+                .map(|pixel| srgb_to_linear(pixel[0] as f32 / 255.0))
+                .sum::<f32>()
+                / 16.0
+        };
+        assert!((mean_light(true) - 0.5).abs() < 0.02);
+        //Blending in sRGB darkens it
+        assert!(mean_light(false) < 0.3);
+    }
+}
@@ -0,0 +1,567 @@
+use super::metadata::{Frame, LayoutMetadata, Placement};
+use super::utils::RectSize;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Sprite sheet descriptors understood by game engines.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum SheetFormat {
+    #[default]
+    JsonHash,
+    JsonArray,
+    Sparrow,
+    Godot,
+    Unity,
+}
+
+impl SheetFormat {
+    pub const ALL: [SheetFormat; 5] = [
+        SheetFormat::JsonHash,
+        SheetFormat::JsonArray,
+        SheetFormat::Sparrow,
+        SheetFormat::Godot,
+        SheetFormat::Unity,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SheetFormat::JsonHash => "JSON-Hash",
+            SheetFormat::JsonArray => "JSON-Array",
+            SheetFormat::Sparrow => "Sparrow",
+            SheetFormat::Godot => "Godot",
+            SheetFormat::Unity => "Unity",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        SheetFormat::ALL
+            .iter()
+            .find(|format| format.name().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+
+    /// Only TexturePacker and Sparrow formats can show rotated regions.
+    pub fn supports_rotation(&self) -> bool {
+        matches!(
+            self,
+            SheetFormat::JsonHash | SheetFormat::JsonArray | SheetFormat::Sparrow
+        )
+    }
+
+    /// Writes descriptor of `layout` to `path` directory, named after its image.
+    /// JSON ones get `_sheet` suffix to keep layout metadata file untouched.
+    /// Godot gets a folder with one AtlasTexture resource per image.
+    /// Fails without writing if format can't show rotated images of layout.
+    /// `sixteen_bit` tells the depth of saved image for formats that describe it.
+    pub fn save(&self, layout: &LayoutMetadata, sixteen_bit: bool, path: &Path) -> io::Result<()> {
+        if !self.supports_rotation() && layout.images.iter().any(|i| i.rotated) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} doesn't support rotated images.", self.name()),
+            ));
+        }
+        let stem = image_stem(layout);
+        match self {
+            SheetFormat::JsonHash | SheetFormat::JsonArray => fs::write(
+                path.join(format!("{}_sheet.{}", stem, "json")),
+                serde_json::to_string_pretty(&self.texture_packer(layout, sixteen_bit))?,
+            ),
+            SheetFormat::Sparrow => {
+                fs::write(path.join(format!("{}.{}", stem, "xml")), sparrow(layout))
+            }
+            SheetFormat::Godot => {
+                let folder = path.join(&stem);
+                fs::create_dir_all(&folder)?;
+                for (name, placement) in frame_names(layout).iter().zip(&layout.images) {
+                    fs::write(
+                        folder.join(format!("{}.{}", name, "tres")),
+                        godot(&layout.image, placement),
+                    )?;
+                }
+                Ok(())
+            }
+            SheetFormat::Unity => {
+                let meta = path.join(format!("{}.{}", layout.image, "meta"));
+                //GUID of existing meta file is kept, so Unity references to atlas stay valid
+                let guid = fs::read_to_string(&meta)
+                    .ok()
+                    .and_then(|yaml| {
+                        yaml.lines()
+                            .find_map(|line| line.strip_prefix("guid:"))
+                            .map(|guid| guid.trim().to_string())
+                    })
+                    .unwrap_or_else(random_guid);
+                fs::write(meta, unity(layout, &guid))
+            }
+        }
+    }
+
+    //TexturePacker keeps frames unrotated, atlas holds them turned clockwise
+    fn texture_packer(&self, layout: &LayoutMetadata, sixteen_bit: bool) -> serde_json::Value {
+        let frames =
+            frame_names(layout)
+                .into_iter()
+                .zip(&layout.images)
+                .map(|(name, placement)| {
+                    let frame = placement.frame;
+                    let (w, h) = placement.unturned();
+                    let (sprite, source) = placement.trim().unwrap_or((
+                        Frame::new(0, 0, w, h),
+                        RectSize::new(w as usize, h as usize),
+                    ));
+                    let mut value = json!({
+                        "frame": {"x": frame.x, "y": frame.y, "w": w, "h": h},
+                        "rotated": placement.rotated,
+                        "trimmed": placement.trim().is_some(),
+                        "spriteSourceSize": sprite,
+                        "sourceSize": source,
+                    });
+                    if let Some(animation) = placement.animation {
+                        value["duration"] = json!(animation.delay);
+                    }
+                    (name, value)
+                });
+        let frames = match self {
+            SheetFormat::JsonArray => serde_json::Value::Array(
+                frames
+                    .map(|(name, mut frame)| {
+                        frame["filename"] = json!(name);
+                        frame
+                    })
+                    .collect(),
+            ),
+            _ => serde_json::Value::Object(frames.collect()),
+        };
+        json!({
+            "frames": frames,
+            "meta": {
+                "app": "PickPicPack",
+                "version": env!("CARGO_PKG_VERSION"),
+                "image": layout.image,
+                "format": match sixteen_bit {
+                    true => "RGBA16161616",
+                    false => "RGBA8888",
+                },
+                "size": {"w": layout.size.w, "h": layout.size.h},
+                "scale": layout.scale.to_string(),
+            },
+        })
+    }
+}
+
+/// Stylesheets for web pages, saved next to any sprite sheet descriptor.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum StyleSheet {
+    #[default]
+    Css,
+    Scss,
+}
+
+impl StyleSheet {
+    pub const ALL: [StyleSheet; 2] = [StyleSheet::Css, StyleSheet::Scss];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StyleSheet::Css => "CSS",
+            StyleSheet::Scss => "SCSS",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        StyleSheet::ALL
+            .iter()
+            .find(|style| style.name().eq_ignore_ascii_case(name.trim()))
+            .copied()
+    }
+
+    /// Writes stylesheet of `layout` to `path` directory, named after its image.
+    pub fn save(&self, layout: &LayoutMetadata, path: &Path) -> io::Result<()> {
+        let stem = image_stem(layout);
+        match self {
+            StyleSheet::Css => fs::write(path.join(format!("{}.{}", stem, "css")), css(layout)),
+            StyleSheet::Scss => fs::write(path.join(format!("{}.{}", stem, "scss")), scss(layout)),
+        }
+    }
+}
+
+fn image_stem(layout: &LayoutMetadata) -> String {
+    Path::new(&layout.image)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default()
+}
+
+/// Names of frames taken from file stems, repeated ones get numbered.
+/// Frames of animations get their index, like `walk_0`.
+pub fn frame_names(layout: &LayoutMetadata) -> Vec<String> {
+    unique(layout.images.iter().map(|placement| {
+        let stem = placement
+            .file
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_else(|| "frame".to_string());
+        match placement.animation {
+            Some(animation) => format!("{}_{}", stem, animation.index),
+            None => stem,
+        }
+    }))
+}
+
+//Repeated names get the first free number, like `coin_1`
+fn unique(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut used = HashSet::<String>::new();
+    names
+        .map(|name| {
+            let mut unique = name.clone();
+            let mut count = 0;
+            while !used.insert(unique.clone()) {
+                count += 1;
+                unique = format!("{}_{}", name, count);
+            }
+            unique
+        })
+        .collect()
+}
+
+//Names stay unique after their characters are replaced
+fn class_names(layout: &LayoutMetadata) -> Vec<String> {
+    unique(frame_names(layout).iter().map(|name| class_name(name)))
+}
+
+//Starling shows rotated regions turned counter-clockwise, that restores them
+fn sparrow(layout: &LayoutMetadata) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    let _ = writeln!(
+        xml,
+        "<TextureAtlas imagePath=\"{}\">",
+        escape(&layout.image)
+    );
+    for (name, placement) in frame_names(layout).iter().zip(&layout.images) {
+        let frame = placement.frame;
+        let _ = write!(
+            xml,
+            "    <SubTexture name=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"",
+            escape(name),
+            frame.x,
+            frame.y,
+            frame.w,
+            frame.h
+        );
+        if placement.rotated {
+            xml.push_str(" rotated=\"true\"");
+        }
+        if let Some((sprite, source)) = placement.trim() {
+            let _ = write!(
+                xml,
+                " frameX=\"-{}\" frameY=\"-{}\" frameWidth=\"{}\" frameHeight=\"{}\"",
+                sprite.x, sprite.y, source.w, source.h
+            );
+        }
+        xml.push_str("/>\n");
+    }
+    xml.push_str("</TextureAtlas>\n");
+    xml
+}
+
+//Margin restores trimmed borders around region
+fn godot(image: &str, placement: &Placement) -> String {
+    let frame = placement.frame;
+    let mut tres = format!(
+        "[gd_resource type=\"AtlasTexture\" load_steps=2 format=2]\n\n\
+         [ext_resource path=\"../{}\" type=\"Texture\" id=1]\n\n\
+         [resource]\n\
+         atlas = ExtResource( 1 )\n\
+         region = Rect2( {}, {}, {}, {} )\n",
+        image, frame.x, frame.y, frame.w, frame.h
+    );
+    if let Some((sprite, source)) = placement.trim() {
+        let _ = writeln!(
+            tres,
+            "margin = Rect2( {}, {}, {}, {} )",
+            sprite.x,
+            sprite.y,
+            source.w as u32 - sprite.w,
+            source.h as u32 - sprite.h
+        );
+    }
+    tres
+}
+
+fn random_guid() -> String {
+    (0..32)
+        .map(|_| std::char::from_digit(rand::thread_rng().gen_range(0..16), 16).unwrap())
+        .collect()
+}
+
+//Unity counts rects from the bottom of texture
+fn unity(layout: &LayoutMetadata, guid: &str) -> String {
+    let mut yaml = format!(
+        "fileFormatVersion: 2\n\
+         guid: {}\n\
+         TextureImporter:\n  \
+           serializedVersion: 11\n  \
+           mipmaps:\n    \
+             enableMipMap: 0\n  \
+           textureType: 8\n  \
+           spriteMode: 2\n  \
+           spritePixelsToUnits: 100\n  \
+           alphaIsTransparency: 1\n  \
+           spriteSheet:\n    \
+             serializedVersion: 2\n    \
+             sprites:\n",
+        guid
+    );
+    for (name, placement) in frame_names(layout).iter().zip(&layout.images) {
+        let frame = placement.frame;
+        let y = (layout.size.h as u32).saturating_sub(frame.y + frame.h);
+        let _ = write!(
+            yaml,
+            "    - serializedVersion: 2\n      \
+               name: {}\n      \
+               rect:\n        \
+                 serializedVersion: 2\n        \
+                 x: {}\n        \
+                 y: {}\n        \
+                 width: {}\n        \
+                 height: {}\n      \
+               alignment: 0\n      \
+               pivot: {{x: 0.5, y: 0.5}}\n      \
+               border: {{x: 0, y: 0, z: 0, w: 0}}\n",
+            name, frame.x, y, frame.w, frame.h
+        );
+    }
+    yaml.push_str("  spritePackingTag:\n");
+    yaml
+}
+
+/// One class per image on top of `.sprite` class that holds the sheet.
+fn css(layout: &LayoutMetadata) -> String {
+    let mut css = format!(
+        ".sprite {{\n    \
+           display: inline-block;\n    \
+           background-image: url(\"{}\");\n    \
+           background-repeat: no-repeat;\n\
+         }}\n",
+        layout.image
+    );
+    for (name, placement) in class_names(layout).iter().zip(&layout.images) {
+        let frame = placement.frame;
+        let _ = write!(
+            css,
+            "\n.sprite-{} {{\n    \
+               width: {}px;\n    \
+               height: {}px;\n    \
+               background-position: {} {};\n\
+             }}\n",
+            name,
+            frame.w,
+            frame.h,
+            offset(frame.x),
+            offset(frame.y)
+        );
+    }
+    css
+}
+
+/// Variable with position and size per image, `sprite` mixin applies one of them.
+fn scss(layout: &LayoutMetadata) -> String {
+    let names = class_names(layout);
+    let mut scss = format!("$sprite-image: url(\"{}\");\n\n", layout.image);
+    for (name, placement) in names.iter().zip(&layout.images) {
+        let frame = placement.frame;
+        let _ = writeln!(
+            scss,
+            "$sprite-{}: {} {} {}px {}px;",
+            name,
+            offset(frame.x),
+            offset(frame.y),
+            frame.w,
+            frame.h
+        );
+    }
+    scss.push_str(
+        "\n@mixin sprite($sprite) {\n    \
+           display: inline-block;\n    \
+           background-image: $sprite-image;\n    \
+           background-repeat: no-repeat;\n    \
+           background-position: nth($sprite, 1) nth($sprite, 2);\n    \
+           width: nth($sprite, 3);\n    \
+           height: nth($sprite, 4);\n\
+         }\n",
+    );
+    for name in &names {
+        let _ = write!(
+            scss,
+            "\n.sprite-{} {{\n    @include sprite($sprite-{});\n}}\n",
+            name, name
+        );
+    }
+    scss
+}
+
+fn offset(value: u32) -> String {
+    match value {
+        0 => "0".to_string(),
+        _ => format!("-{}px", value),
+    }
+}
+
+//Everything but letters, digits, `-` and `_` becomes `-`
+fn class_name(name: &str) -> String {
+    name.chars()
+        .map(
+            |c| match c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                true => c,
+                false => '-',
+            },
+        )
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn placement(file: &str, frame: Frame, rotated: bool) -> Placement {
+        let (w, h) = match rotated {
+            true => (frame.h, frame.w),
+            false => (frame.w, frame.h),
+        };
+        Placement {
+            file: PathBuf::from(file),
+            original: RectSize::new(w as usize, h as usize),
+            source: Frame::new(0, 0, w, h),
+            frame,
+            rotated,
+            animation: None,
+        }
+    }
+
+    //Hero stands upright, coin of 6x8 is turned on the sheet
+    fn layout(rotated: bool) -> LayoutMetadata {
+        LayoutMetadata {
+            image: "atlas.png".to_string(),
+            size: RectSize::new(64, 64),
+            scale: 0.5,
+            extrude: 0,
+            images: vec![
+                placement("hero.png", Frame::new(0, 0, 10, 20), false),
+                placement("coin.png", Frame::new(10, 0, 8, 6), rotated),
+            ],
+        }
+    }
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn texture_packer_describes_frames() {
+        let hash = SheetFormat::JsonHash.texture_packer(&layout(true), false);
+        assert_eq!(
+            hash["frames"]["hero"]["frame"],
+            json!({"x": 0, "y": 0, "w": 10, "h": 20})
+        );
+        assert_eq!(hash["frames"]["hero"]["rotated"], false);
+        //Frame size is unrotated, position is on the sheet
+        assert_eq!(
+            hash["frames"]["coin"]["frame"],
+            json!({"x": 10, "y": 0, "w": 6, "h": 8})
+        );
+        assert_eq!(hash["frames"]["coin"]["rotated"], true);
+        assert_eq!(hash["meta"]["scale"], "0.5");
+        assert_eq!(hash["meta"]["format"], "RGBA8888");
+
+        let array = SheetFormat::JsonArray.texture_packer(&layout(true), true);
+        assert_eq!(array["frames"][0]["filename"], "hero");
+        assert_eq!(array["frames"][1]["filename"], "coin");
+        assert_eq!(array["frames"][1]["rotated"], true);
+        assert_eq!(array["meta"]["format"], "RGBA16161616");
+    }
+
+    #[test]
+    fn sparrow_describes_frames() {
+        let xml = sparrow(&layout(true));
+        assert!(xml.contains("<TextureAtlas imagePath=\"atlas.png\">"));
+        assert!(xml.contains("name=\"hero\" x=\"0\" y=\"0\" width=\"10\" height=\"20\"/>"));
+        assert!(xml.contains(
+            "name=\"coin\" x=\"10\" y=\"0\" width=\"8\" height=\"6\" rotated=\"true\"/>"
+        ));
+    }
+
+    #[test]
+    fn godot_and_unity_describe_frames() {
+        let layout = layout(false);
+        let tres = godot(&layout.image, &layout.images[1]);
+        assert!(tres.contains("path=\"../atlas.png\""));
+        assert!(tres.contains("region = Rect2( 10, 0, 8, 6 )"));
+
+        let yaml = unity(&layout, "0123456789abcdef0123456789abcdef");
+        assert!(yaml.contains("guid: 0123456789abcdef0123456789abcdef"));
+        //Rects are counted from the bottom
+        assert!(yaml.contains("name: hero\n      rect:\n        serializedVersion: 2\n        x: 0\n        y: 44\n        width: 10\n        height: 20\n"));
+        assert!(yaml.contains("name: coin\n      rect:\n        serializedVersion: 2\n        x: 10\n        y: 58\n        width: 8\n        height: 6\n"));
+    }
+
+    #[test]
+    fn godot_and_unity_refuse_rotated_layout() {
+        let folder = temp_folder("ppp_sheets_rotated");
+        for format in [SheetFormat::Godot, SheetFormat::Unity] {
+            assert!(format.save(&layout(true), false, &folder).is_err());
+        }
+        assert_eq!(fs::read_dir(&folder).unwrap().count(), 0);
+        assert!(SheetFormat::Sparrow
+            .save(&layout(true), false, &folder)
+            .is_ok());
+    }
+
+    #[test]
+    fn unity_keeps_existing_guid() {
+        let folder = temp_folder("ppp_sheets_guid");
+        let meta = folder.join("atlas.png.meta");
+        fs::write(
+            &meta,
+            "fileFormatVersion: 2\nguid: 0123456789abcdef0123456789abcdef\n",
+        )
+        .unwrap();
+        SheetFormat::Unity
+            .save(&layout(false), false, &folder)
+            .unwrap();
+        let yaml = fs::read_to_string(&meta).unwrap();
+        assert!(yaml.contains("guid: 0123456789abcdef0123456789abcdef\n"));
+        assert!(yaml.contains("name: coin"));
+    }
+
+    #[test]
+    fn names_stay_unique_after_sanitizing() {
+        let mut layout = layout(false);
+        layout.images = ["a b.png", "a-b.png", "x/a-b.png", "a-b_1.png"]
+            .iter()
+            .map(|file| placement(file, Frame::new(0, 0, 4, 4), false))
+            .collect();
+        assert_eq!(frame_names(&layout), ["a b", "a-b", "a-b_1", "a-b_1_1"]);
+        let names = class_names(&layout);
+        assert_eq!(names, ["a-b", "a-b_1", "a-b_1_1", "a-b_1_1_1"]);
+        for name in &names {
+            assert!(css(&layout).contains(&format!(".sprite-{} {{", name)));
+            assert!(scss(&layout).contains(&format!("$sprite-{}:", name)));
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use super::resampling::{linear_to_srgb, Canvas};
+use image::Rgba;
+use serde::{Deserialize, Serialize};
+
+/// Curves that bring light values above white of HDR images to displayable range.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum ToneMapping {
+    #[default]
+    Clamp,
+    Reinhard,
+    AcesFilmic,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [
+        ToneMapping::Clamp,
+        ToneMapping::Reinhard,
+        ToneMapping::AcesFilmic,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::Clamp => "Clamp",
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::AcesFilmic => "ACES Filmic",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.trim().replace(['-', '_', ' '], "");
+        ToneMapping::ALL
+            .iter()
+            .find(|curve| {
+                //First word is enough, like "aces"
+                curve.name().replace(' ', "").eq_ignore_ascii_case(&name)
+                    || curve
+                        .name()
+                        .split(' ')
+                        .next()
+                        .is_some_and(|word| word.eq_ignore_ascii_case(&name))
+            })
+            .copied()
+    }
+
+    fn map(&self, value: f32) -> f32 {
+        match self {
+            ToneMapping::Clamp => value,
+            ToneMapping::Reinhard => value / (1.0 + value),
+            //Narkowicz fit of ACES reference curve, it passes white on bright light
+            ToneMapping::AcesFilmic => {
+                ((value * (2.51 * value + 0.03)) / (value * (2.43 * value + 0.59) + 0.14)).min(1.0)
+            }
+        }
+    }
+}
+
+/// `exposure` is in stops, every one doubles brightness before the curve.
+#[derive(Default, PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ToneMapSettings {
+    pub curve: ToneMapping,
+    pub exposure: f32,
+}
+
+impl ToneMapSettings {
+    /// Turns linear light `pixels` in rows of `w` into sRGB image with 16 bits per channel.
+    pub fn apply(&self, w: u32, h: u32, pixels: &[[f32; 4]]) -> Canvas<u16> {
+        let gain = 2f32.powf(self.exposure);
+        let quantize = |value: f32| (value.clamp(0.0, 1.0) * 65535.0).round() as u16;
+        let encode = |value: f32| {
+            let mapped = self.curve.map((value * gain).max(0.0));
+            quantize(linear_to_srgb(mapped.clamp(0.0, 1.0)))
+        };
+        Canvas::<u16>::from_fn(w, h, |x, y| {
+            let [r, g, b, a] = pixels[(y * w + x) as usize];
+            Rgba([encode(r), encode(g), encode(b), quantize(a)])
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn curves_start_at_zero_and_rise() {
+        for curve in ToneMapping::ALL {
+            assert!(curve.map(0.0).abs() < 1e-6, "{}", curve.name());
+            let values: Vec<f32> = (1..=200).map(|i| curve.map(i as f32 * 0.05)).collect();
+            assert!(
+                values.windows(2).all(|pair| pair[1] >= pair[0]),
+                "{} falls",
+                curve.name()
+            );
+        }
+    }
+
+    #[test]
+    fn curves_keep_white_below_one() {
+        //Clamp keeps white as it is, others leave room for brighter light
+        assert_eq!(ToneMapping::Clamp.map(1.0), 1.0);
+        for curve in [ToneMapping::Reinhard, ToneMapping::AcesFilmic] {
+            assert!(curve.map(1.0) < 1.0, "{}", curve.name());
+            assert!(curve.map(100.0) <= 1.0, "{}", curve.name());
+        }
+    }
+
+    #[test]
+    fn exposure_doubles_light_per_stop() {
+        let pixels = [[0.25, 0.25, 0.25, 1.0], [4.0, 0.0, 0.0, 0.5]];
+        let plain = ToneMapSettings::default().apply(2, 1, &pixels);
+        let brighter = ToneMapSettings {
+            exposure: 1.0,
+            ..Default::default()
+        }
+        .apply(2, 1, &pixels);
+        let half = (linear_to_srgb(0.5) * 65535.0).round() as u16;
+        assert_eq!(brighter.get_pixel(0, 0)[0], half);
+        assert!(plain.get_pixel(0, 0)[0] < half);
+        //Clamped light stays white, alpha is not tone mapped
+        assert_eq!(plain.get_pixel(1, 0).0, [65535, 0, 0, 32768]);
+    }
+}
@@ -0,0 +1,136 @@
+use super::metadata::{Frame, LayoutMetadata};
+use super::sheets::frame_names;
+use image::imageops::{crop_imm, rotate270};
+use image::{ImageError, ImageResult, RgbaImage};
+use std::io;
+use std::path::Path;
+
+/// Cuts every image described by layout metadata out of `atlas` and saves it to `output`.
+/// Images are named after their source files, rotated ones are turned back.
+pub fn unpack(atlas: &Path, layout: &LayoutMetadata, output: &Path) -> ImageResult<usize> {
+    let atlas = image::open(atlas)?.to_rgba8();
+    for (name, placement) in frame_names(layout).iter().zip(&layout.images) {
+        let region = cut(&atlas, placement.frame)?;
+        let region = match placement.rotated {
+            true => rotate270(&region),
+            false => region,
+        };
+        region.save(output.join(format!("{}.{}", name, "png")))?;
+    }
+    Ok(layout.images.len())
+}
+
+/// Cuts `atlas` to uniform cells, counting from the top left corner.
+/// With only one of `columns` and `rows` given, cells are square. Empty cells are skipped.
+pub fn slice(
+    atlas: &Path,
+    columns: Option<u32>,
+    rows: Option<u32>,
+    output: &Path,
+) -> ImageResult<usize> {
+    let stem = atlas
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let atlas = image::open(atlas)?.to_rgba8();
+    let (w, h) = atlas.dimensions();
+    let (cell_w, cell_h) = match (columns, rows) {
+        (Some(columns), Some(rows)) => (w / columns.max(1), h / rows.max(1)),
+        (Some(columns), None) => (w / columns.max(1), w / columns.max(1)),
+        (None, Some(rows)) => (h / rows.max(1), h / rows.max(1)),
+        (None, None) => return Err(invalid("Number of columns or rows is not set.")),
+    };
+    if cell_w == 0 || cell_h == 0 {
+        return Err(invalid("Cells are smaller than a pixel."));
+    }
+    let mut saved = 0;
+    for row in 0..h / cell_h {
+        for column in 0..w / cell_w {
+            let cell = cut(
+                &atlas,
+                Frame::new(column * cell_w, row * cell_h, cell_w, cell_h),
+            )?;
+            if cell.pixels().all(|pixel| pixel[3] == 0) {
+                continue;
+            }
+            cell.save(output.join(format!("{}_{}_{}.{}", stem, row, column, "png")))?;
+            saved += 1;
+        }
+    }
+    Ok(saved)
+}
+
+fn cut(atlas: &RgbaImage, frame: Frame) -> ImageResult<RgbaImage> {
+    if frame.x + frame.w > atlas.width() || frame.y + frame.h > atlas.height() {
+        return Err(invalid("Region is out of image bounds."));
+    }
+    Ok(crop_imm(atlas, frame.x, frame.y, frame.w, frame.h).to_image())
+}
+
+fn invalid(message: &str) -> ImageError {
+    ImageError::IoError(io::Error::new(io::ErrorKind::InvalidInput, message))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metadata::Placement;
+    use crate::utils::RectSize;
+    use image::imageops::{overlay, rotate90};
+    use image::Rgba;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn temp_folder(name: &str) -> PathBuf {
+        let folder = std::env::temp_dir().join(name);
+        let _ = fs::remove_dir_all(&folder);
+        fs::create_dir_all(&folder).unwrap();
+        folder
+    }
+
+    #[test]
+    fn rotated_image_is_turned_back() {
+        let folder = temp_folder("ppp_unpack_rotated");
+        let original = RgbaImage::from_fn(3, 2, |x, y| Rgba([x as u8 * 80, y as u8 * 120, 7, 255]));
+        //Atlas holds image turned clockwise
+        let mut atlas = RgbaImage::new(6, 6);
+        overlay(&mut atlas, &rotate90(&original), 1, 1);
+        atlas.save(folder.join("atlas.png")).unwrap();
+        let layout = LayoutMetadata {
+            image: "atlas.png".to_string(),
+            size: RectSize::new(6, 6),
+            scale: 1.0,
+            extrude: 0,
+            images: vec![Placement {
+                file: PathBuf::from("icon.png"),
+                original: RectSize::new(3, 2),
+                source: Frame::new(0, 0, 3, 2),
+                frame: Frame::new(1, 1, 2, 3),
+                rotated: true,
+                animation: None,
+            }],
+        };
+        assert_eq!(
+            unpack(&folder.join("atlas.png"), &layout, &folder).unwrap(),
+            1
+        );
+        let unpacked = image::open(folder.join("icon.png")).unwrap().to_rgba8();
+        assert_eq!(unpacked, original);
+    }
+
+    #[test]
+    fn slice_skips_transparent_cells() {
+        let folder = temp_folder("ppp_slice");
+        let atlas = RgbaImage::from_fn(4, 2, |x, _| match x < 2 {
+            true => Rgba([200, 10, 10, 255]),
+            false => Rgba([200, 10, 10, 0]),
+        });
+        atlas.save(folder.join("tiles.png")).unwrap();
+        assert_eq!(
+            slice(&folder.join("tiles.png"), Some(2), None, &folder).unwrap(),
+            1
+        );
+        assert!(folder.join("tiles_0_0.png").is_file());
+        assert!(!folder.join("tiles_0_1.png").exists());
+    }
+}
@@ -72,7 +72,7 @@ pub enum ImageScaling {
     Actual,
 }
 
-#[derive(Default, Debug, Clone, Copy)]
+#[derive(Default, Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct RectSize {
     pub w: usize,
     pub h: usize,
@@ -181,3 +181,13 @@ pub fn parse_custom_ratio(raw_text: &str) -> (usize, usize) {
 
     (x.min(100), y.min(100))
 }
+
+/// Color from hex text like `FF8800` or `#ff8800`.
+pub fn parse_color(raw_text: &str) -> Option<[u8; 3]> {
+    let hex = raw_text.trim().trim_start_matches('#');
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}